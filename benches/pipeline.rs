@@ -0,0 +1,76 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use criterion::{BenchmarkId, criterion_group, criterion_main, Criterion, Throughput};
+use regex::Regex;
+use tokio::runtime::Runtime;
+
+use kenshi::data_handler::ListenData;
+use kenshi::data_type::{HttpResp, ScanMode};
+use kenshi::expr::Expr;
+use kenshi::ScanStats;
+
+const ITEMS: usize = 10_000;
+const QUEUE_SIZE: usize = 1024;
+
+fn resp(i: usize) -> HttpResp {
+    let html = format!("<html><head><title>Page {i}</title></head><body>{}</body></html>", "lorem ipsum dolor sit amet ".repeat(16));
+    HttpResp {
+        payload: format!("word{i}"),
+        url: format!("https://example.com/word{i}"),
+        // one hit in ten
+        status: match i % 10 {
+            0 => 200,
+            _ => 404,
+        },
+        html: html.into_bytes(),
+        ttfb: Duration::from_millis(20),
+        duration: Duration::from_millis(25),
+        remote_addr: None,
+        content_type: Some("text/html; charset=utf-8".to_string()),
+        location: None,
+        server: Some("nginx".to_string()),
+        headers: vec![("content-type".to_string(), "text/html; charset=utf-8".to_string())],
+    }
+}
+
+// the scanner side: responses go through the bounded channel to the handler workers
+fn pipeline(rt: &Runtime, workers: usize, configure: impl Fn(&mut ListenData), matches: Option<Expr>) {
+    let (print_tx, _print_rx) = std::sync::mpsc::channel();
+    let mut listen_data = ListenData::new(print_tx, ScanMode::Silent, Arc::new(ScanStats::new()));
+    listen_data.set_terminal(false);
+    configure(&mut listen_data);
+    let (tx, rx) = tokio::sync::mpsc::channel::<HttpResp>(QUEUE_SIZE);
+    listen_data.handler(rx, workers, matches, None);
+    rt.block_on(async {
+        for i in 0..ITEMS {
+            tx.send(resp(i)).await.unwrap();
+        }
+    });
+    drop(tx);
+    listen_data.waiting();
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let matches = || Some(Expr::parse(r#"status == 200 && !body ~ "Not Found""#).unwrap());
+    let title = Regex::new(r"<title>([^<]+)</title>").unwrap();
+    let mut group = c.benchmark_group("response_pipeline");
+    group.throughput(Throughput::Elements(ITEMS as u64));
+    group.sample_size(20);
+    for workers in [1, 4] {
+        group.bench_function(BenchmarkId::new("match", workers), |b| {
+            b.iter(|| pipeline(&rt, workers, |_| {}, matches()))
+        });
+        group.bench_function(BenchmarkId::new("match_extract_cluster", workers), |b| {
+            b.iter(|| pipeline(&rt, workers, |listen_data| {
+                listen_data.use_extract(vec![title.clone()]);
+                listen_data.use_clusters(5);
+            }, matches()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
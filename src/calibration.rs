@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufRead;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use futures::future::join_all;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use reqwest::{Client, header};
+use tokio::sync::OnceCell;
+
+use crate::data_type::{Fingerprint, ScanStats, Stats};
+use crate::lru::{host_of, LruCache};
+use crate::ratelimit::Limits;
+use crate::rawhttp::RequestTemplate;
+use crate::throttle::{is_throttled, parse_retry_after};
+use crate::wordlist::{DEFAULT_KEYWORD, Payload};
+
+
+// random paths nobody would serve, with and without a file extension
+const CALIBRATION_PROBES: [(usize, &str); 6] = [
+    (12, ""),
+    (20, ""),
+    (32, ""),
+    (16, ".php"),
+    (16, ".html"),
+    (16, ".bak"),
+];
+
+// fewer per host, each one is an extra request to every host, counted as Stats::Probes
+const HOST_CALIBRATION_PROBES: [(usize, &str); 3] = [
+    (12, ""),
+    (20, ""),
+    (16, ".html"),
+];
+
+fn random_word(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(|c| (c as char).to_ascii_lowercase())
+        .collect()
+}
+
+impl Fingerprint {
+    /// Shape of one response, every field is set
+    pub fn of(status: u16, body: &[u8]) -> Self {
+        Self {
+            status,
+            size: Some(body.len()),
+            lines: Some(body.lines().count()),
+            words: Some(String::from_utf8_lossy(body).split_whitespace().count()),
+        }
+    }
+
+    /// Only the fields that are set are compared
+    pub fn matches(&self, shape: &Fingerprint) -> bool {
+        self.status == shape.status
+            && (self.size.is_none() || self.size == shape.size)
+            && (self.lines.is_none() || self.lines == shape.lines)
+            && (self.words.is_none() || self.words == shape.words)
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "status: {}", self.status)?;
+        if let Some(n) = self.size {
+            write!(f, ", size: {n}")?;
+        }
+        if let Some(n) = self.lines {
+            write!(f, ", lines: {n}")?;
+        }
+        if let Some(n) = self.words {
+            write!(f, ", words: {n}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Reduce the probe responses to filters. Per status code, the first of size,
+/// words and lines that stays the same across the probes is kept, pages that
+/// echo the random path back change size but usually not their word count.
+pub fn fingerprints(shapes: &[Fingerprint]) -> Vec<Fingerprint> {
+    let mut groups: BTreeMap<u16, Vec<&Fingerprint>> = BTreeMap::new();
+    for shape in shapes {
+        groups.entry(shape.status).or_default().push(shape);
+    }
+    let mut fingerprints = vec![];
+    for (status, group) in groups {
+        let same = |field: fn(&Fingerprint) -> Option<usize>| {
+            let first = field(group[0]);
+            group.iter().all(|s| field(s) == first).then_some(first).flatten()
+        };
+        let fingerprint = if let Some(size) = same(|s| s.size) {
+            Fingerprint { status, size: Some(size), lines: None, words: None }
+        } else if let Some(words) = same(|s| s.words) {
+            Fingerprint { status, size: None, lines: None, words: Some(words) }
+        } else if let Some(lines) = same(|s| s.lines) {
+            Fingerprint { status, size: None, lines: Some(lines), words: None }
+        } else {
+            log::warn!("[calibration] status {status} responses are not stable, no filter added.");
+            continue;
+        };
+        fingerprints.push(fingerprint);
+    }
+    fingerprints
+}
+
+// Send one random probe and return the shape of the answer
+async fn probe_one(client: &Client, limits: &Limits, stats: &ScanStats, request: &RequestTemplate,
+                   keywords: &[String], (len, ext): (usize, &str)) -> Option<(String, Fingerprint)> {
+    let word = random_word(len) + ext;
+    let payload = Payload {
+        words: keywords.iter().map(|k| (k.clone(), word.clone())).collect(),
+    };
+    let probe = request.fill(&payload);
+    let request = match probe.build(client) {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("[calibration] {} {}", probe.url, e);
+            return None;
+        }
+    };
+    // same limits as the payloads, None once the host was given up
+    let _permit = limits.acquire(&probe.url).await?;
+    stats.inc(&Stats::Probes);
+    match client.execute(request).await {
+        Ok(r) => {
+            let status = r.status().as_u16();
+            if let Some(backoff) = &limits.backoff {
+                let retry_after = r.headers().get(header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok());
+                if is_throttled(status, retry_after.is_some()) {
+                    // a throttled answer says nothing about missing pages
+                    stats.inc(&Stats::Throttled);
+                    let retry_after = retry_after.and_then(|v| parse_retry_after(v, SystemTime::now()));
+                    backoff.throttled(&probe.url, retry_after);
+                    return None;
+                }
+            }
+            match r.bytes().await {
+                Ok(body) => Some((probe.url, Fingerprint::of(status, &body))),
+                Err(e) => {
+                    log::warn!("[calibration] {} {}", probe.url, e);
+                    None
+                }
+            }
+        }
+        Err(e) => {
+            log::warn!("[calibration] {} {}", probe.url, e);
+            None
+        }
+    }
+}
+
+/// Send the probes together and return the shape of each answer
+async fn probe(client: &Client, limits: &Limits, stats: &ScanStats, request: &RequestTemplate,
+               keywords: &[String], probes: &[(usize, &str)]) -> Vec<(String, Fingerprint)> {
+    join_all(probes.iter().map(|p| probe_one(client, limits, stats, request, keywords, *p))).await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Send the random probes and fingerprint what the target answers for paths that don't exist.
+pub async fn calibrate(client: &Client, limits: &Limits, stats: &ScanStats, request: &RequestTemplate,
+                       keywords: &[String]) -> Vec<Fingerprint> {
+    let shapes = probe(client, limits, stats, request, keywords, &CALIBRATION_PROBES).await;
+    for (url, shape) in shapes.iter() {
+        log::info!("[calibration] probe {} {}", url, shape);
+    }
+    let shapes: Vec<Fingerprint> = shapes.into_iter().map(|(_, shape)| shape).collect();
+    let fingerprints = fingerprints(&shapes);
+    for fingerprint in fingerprints.iter() {
+        log::info!("[calibration] filter {}", fingerprint);
+    }
+    fingerprints
+}
+
+type Baseline = Arc<OnceCell<Vec<Fingerprint>>>;
+
+/// Random-path baselines of each host, fetched the first time one of its responses needs matching.
+pub struct HostBaselines {
+    cache: Mutex<LruCache<Baseline>>,
+}
+
+impl HostBaselines {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Fingerprints of the host the request goes to, concurrent callers share one fetch.
+    pub async fn get(&self, client: &Client, limits: &Limits, stats: &ScanStats, request: &RequestTemplate) -> Vec<Fingerprint> {
+        let origin = match host_of(&request.url) {
+            Some(origin) => origin,
+            None => return vec![],
+        };
+        let baseline = {
+            let mut cache = self.cache.lock().unwrap();
+            match cache.get(&origin) {
+                Some(b) => b,
+                None => {
+                    let baseline = Baseline::default();
+                    cache.insert(&origin, baseline.clone());
+                    baseline
+                }
+            }
+        };
+        baseline.get_or_init(|| async {
+            // same request, the path is replaced by a random one
+            let template = RequestTemplate {
+                url: format!("{origin}/{DEFAULT_KEYWORD}"),
+                ..request.clone()
+            };
+            let keywords = [DEFAULT_KEYWORD.to_string()];
+            let shapes: Vec<Fingerprint> = probe(client, limits, stats, &template, &keywords, &HOST_CALIBRATION_PROBES).await
+                .into_iter()
+                .map(|(_, shape)| shape)
+                .collect();
+            let fingerprints = fingerprints(&shapes);
+            log::info!("[calibration] {origin} baseline: {}",
+                fingerprints.iter().map(|f| f.to_string()).collect::<Vec<String>>().join("; "));
+            fingerprints
+        }).await.clone()
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::data_type::{ScanStats, Stats};
+use crate::wordlist::WordlistSet;
+
+pub const CHECKPOINT_DIR: &str = "data";
+// seconds between two checkpoint writes
+pub const CHECKPOINT_INTERVAL: u64 = 10;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub id: String,
+    // command line of the interrupted scan
+    pub args: Vec<String>,
+    // the payloads done, they aren't sent again
+    pub progress: Progress,
+    // payloads and wordlist file sizes, a resume needs the same wordlists
+    pub total: u64,
+    pub wordlist_sizes: Vec<u64>,
+    pub stats: Vec<(Stats, u32)>,
+}
+
+impl Checkpoint {
+    pub fn new(id: &str, args: &[String]) -> Self {
+        Self {
+            id: id.to_string(),
+            args: args.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    pub fn new_id() -> String {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        format!("{:x}{:04x}", now, rand::thread_rng().gen::<u16>())
+    }
+
+    pub fn path(id: &str) -> String {
+        format!("{CHECKPOINT_DIR}/{id}.ckpt")
+    }
+
+    pub fn hits_path(id: &str) -> String {
+        format!("{CHECKPOINT_DIR}/{id}.hits")
+    }
+
+    pub fn load(id: &str) -> Result<Self, String> {
+        let path = Self::path(id);
+        let data = fs::read(&path).map_err(|e| format!("[resume] {path} read failed. {e}"))?;
+        bincode::deserialize(&data).map_err(|e| format!("[resume] {path} is corrupted. {e}"))
+    }
+
+    /// Snapshot the scan counters and write the checkpoint.
+    pub fn save(&mut self, stats: &ScanStats) -> std::io::Result<()> {
+        self.stats = stats.snapshot();
+        let data = bincode::serialize(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        // write then rename, a crash never leaves a half written checkpoint
+        let path = Self::path(&self.id);
+        let tmp_path = format!("{path}.tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        writer.write_all(&data)?;
+        writer.flush()?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Tie the checkpoint to the wordlists. A resumed scan refuses others,
+    /// the saved positions would point at other payloads.
+    pub fn use_wordlist(&mut self, wordlist: &WordlistSet, resumed: bool) -> Result<(), String> {
+        let total = wordlist.len() as u64;
+        let sizes = wordlist.file_sizes();
+        if resumed && (total != self.total || sizes != self.wordlist_sizes) {
+            return Err(format!("[resume] the wordlists changed since the scan was interrupted, {} payloads then, {total} now.", self.total));
+        }
+        self.total = total;
+        self.wordlist_sizes = sizes;
+        Ok(())
+    }
+
+    /// Put the saved counters back into the scan stats. They only count the
+    /// payloads done, the others are sent again.
+    pub fn restore_stats(&self, stats: &ScanStats) {
+        for (stat, n) in self.stats.iter() {
+            stats.set(stat, *n);
+        }
+    }
+
+    /// The scan is over, its checkpoint and the hits kept for the resume go
+    pub fn remove(&self) {
+        let _ = fs::remove_file(Self::path(&self.id));
+        let _ = fs::remove_file(Self::hits_path(&self.id));
+    }
+}
+
+/// Payloads finish out of order, the position only moves
+/// once every payload before it is done.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Progress {
+    // every payload before this index is done
+    pub position: u64,
+    // done past the position
+    done: BTreeSet<u64>,
+}
+
+impl Progress {
+    pub fn new(position: u64) -> Self {
+        Self {
+            position,
+            done: BTreeSet::new(),
+        }
+    }
+
+    pub fn is_done(&self, index: u64) -> bool {
+        index < self.position || self.done.contains(&index)
+    }
+
+    /// Number of payloads done
+    pub fn count(&self) -> u64 {
+        self.position + self.done.len() as u64
+    }
+
+    pub fn complete(&mut self, index: u64) {
+        if index != self.position {
+            self.done.insert(index);
+            return;
+        }
+        self.position += 1;
+        while self.done.remove(&self.position) {
+            self.position += 1;
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// a few tokens may change between two pages of the same cluster, eg. the echoed path
+pub const CLUSTER_DISTANCE: u32 = 6;
+// each hit is compared with every cluster, past this many new pages aren't grouped anymore
+pub const MAX_CLUSTERS: usize = 10_000;
+
+// FNV-1a, stable across runs unlike the std hasher
+fn token_hash(token: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in token.bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// 64 bit simhash over the words of the body, similar pages are a few bits apart.
+/// Numbers are folded into one token, timestamps and counters don't move the hash.
+pub fn simhash(body: &[u8]) -> u64 {
+    let body = String::from_utf8_lossy(body).to_lowercase();
+    let mut weights = [0i64; 64];
+    for token in body.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()) {
+        let hash = if token.chars().any(|c| c.is_ascii_digit()) {
+            token_hash("0")
+        } else {
+            token_hash(token)
+        };
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+    weights.iter()
+        .enumerate()
+        .filter(|(_, w)| **w > 0)
+        .fold(0, |hash, (bit, _)| hash | 1 << bit)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Membership {
+    // reported as usual
+    Keep,
+    // the cluster just outgrew the limit, the following members are dropped
+    Overflow { representative: String, members: usize },
+    Drop,
+}
+
+#[derive(Debug)]
+struct Cluster {
+    status: u16,
+    hash: u64,
+    // first hit of the cluster, it stands for the suppressed ones
+    representative: String,
+    members: usize,
+}
+
+/// Groups the hits by status code and simhash. A cluster with more than
+/// `max_members` hits is suppressed, only its first hits are reported.
+/// Once there are `max_clusters`, hits unlike all of them are reported ungrouped.
+#[derive(Debug)]
+pub struct Clusters {
+    max_members: usize,
+    max_clusters: usize,
+    clusters: Vec<Cluster>,
+    // the warning is logged once
+    full: bool,
+}
+
+impl Clusters {
+    pub fn new(max_members: usize, max_clusters: usize) -> Self {
+        Self {
+            max_members: max_members.max(1),
+            max_clusters,
+            clusters: vec![],
+            full: false,
+        }
+    }
+
+    pub fn add(&mut self, status: u16, hash: u64, url: &str) -> Membership {
+        let cluster = self.clusters.iter_mut()
+            .find(|c| c.status == status && (c.hash ^ hash).count_ones() <= CLUSTER_DISTANCE);
+        let cluster = match cluster {
+            Some(c) => c,
+            None => {
+                if self.clusters.len() >= self.max_clusters {
+                    if !self.full {
+                        log::warn!("[cluster] {} clusters, new pages are no longer grouped", self.max_clusters);
+                        self.full = true;
+                    }
+                    return Membership::Keep;
+                }
+                self.clusters.push(Cluster {
+                    status,
+                    hash,
+                    representative: url.to_string(),
+                    members: 1,
+                });
+                return Membership::Keep;
+            }
+        };
+        cluster.members += 1;
+        match cluster.members {
+            n if n <= self.max_members => Membership::Keep,
+            n if n == self.max_members + 1 => Membership::Overflow {
+                representative: cluster.representative.clone(),
+                members: n,
+            },
+            _ => Membership::Drop,
+        }
+    }
+
+    /// Suppressed clusters, representative url and member count
+    pub fn suppressed(&self) -> Vec<(String, usize)> {
+        self.clusters.iter()
+            .filter(|c| c.members > self.max_members)
+            .map(|c| (c.representative.clone(), c.members))
+            .collect()
+    }
+}
@@ -1,5 +1,16 @@
 use std::fs;
-use crate::data_type::{FilterRules, Matches};
+
+pub const G_DEFAULT_CONCURRENT_NUM: usize = 500;
+pub const G_DEFAULT_MIN_CONCURRENCY: usize = 10;
+pub const G_DEFAULT_MAX_CONCURRENCY: usize = 2000;
+// pauses in a row before a throttling host is given up on
+pub const G_DEFAULT_MAX_THROTTLE: usize = 5;
+// first delay before a retry, doubled each time
+pub const G_DEFAULT_RETRY_DELAY_MS: u64 = 200;
+pub const G_DEFAULT_WORKER_NUM: usize = 4;
+// responses waiting for the workers, requests pause when it is full
+pub const G_DEFAULT_QUEUE_SIZE: usize = 1024;
+pub const G_DEFAULT_REQUEST_TIMEOUT: u64 = 10;
 
 pub const COMMON_USER_AGENTS: [&str; 4] = [
     "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/111.0.0.0 Safari/537.36",
@@ -54,169 +65,6 @@ pub fn file_exists(filename: &str) -> bool {
     fs::metadata(filename).is_ok()
 }
 
-pub fn is_match(raw_str: &str, matches: &Matches) -> bool {
-    let html = raw_str;
-    let mut is_match = true;
-    let mut or_or_or = 0u8;
-    // status code match
-    /*
-    if matches.status_code.contains(&resp.status) {
-        is_match = true
-    } else {
-        return None
-    }
-     */
-
-    // regex match
-    if let Some(regex) = &matches.regex {
-        let n = regex.is_match(&html);
-        is_match = n && is_match;
-        n.then(||{ or_or_or += 1});
-    }
-    // line match
-    if let Some(n) = &matches.line_num {
-        let n = html.lines().count().eq(n);
-        is_match = n && is_match;
-        n.then(||{ or_or_or += 1});
-    }
-
-    // response size
-    if let Some(n) = &matches.resp_size {
-        let n = html.len().eq(n);
-        is_match = n && is_match;
-        n.then(||{ or_or_or += 1});
-    }
-
-    if !matches.and_and_and && or_or_or.gt(&0) {
-        is_match = true
-    }
-    if is_match {
-        return true
-    }
-    false
-}
-
-pub fn is_filter(raw_str: &str, matches: &FilterRules) -> bool {
-    let html = raw_str;
-    let mut is_filter = true;
-    let mut or_or_or = 0u8;
-    // status code match
-    /*
-    if matches.status_code.contains(&resp.status) {
-        is_match = true
-    } else {
-        return None
-    }
-    */
-
-    // regex match
-    if let Some(regex) = &matches.regex {
-        let n = regex.is_match(&html);
-        is_filter = n && is_filter;
-        n.then(||{ or_or_or += 1});
-    }
-    // line match
-    if let Some(fls) = &matches.line_num {
-        let ret = fls.contains(&html.lines().count());
-        is_filter = ret && is_filter;
-        ret.then(||{ or_or_or += 1});
-    }
-
-    // response size
-    if let Some(list) = &matches.resp_size {
-        let resp_size = html.len();
-        let mut ret = false;
-        for v in list.iter() {
-            let range_str: Vec<&str> = v.split('-').collect();
-            if range_str.len().eq(&2) {
-                let mut start = 0;
-                let mut end = 0;
-                if let Ok(u) = range_str.first().unwrap().parse::<usize>() {
-                    start = u
-                }else {
-                    continue
-                }
-                if let Ok(u) = range_str.last().unwrap().parse::<usize>() {
-                    end = u
-                }else {
-                    continue
-                }
-                ret = resp_size >= start && resp_size <= end;
-                if ret {
-                    break
-                }
-            }else {
-                let size_str: String = v.chars()
-                    .filter(|c| c.is_numeric())
-                    .collect();
-                let mut filter_size = 0usize;
-                if let Ok(u) = size_str.parse::<usize>() {
-                    filter_size = u
-                }else {
-                    continue
-                }
-                if v.contains('>') {
-                    ret = resp_size.gt(&filter_size);
-                }else if v.contains('<') {
-                    ret = resp_size.lt(&filter_size);
-                }else {
-                    ret = resp_size.eq(&filter_size);
-                }
-                if ret {
-                    break
-                }
-            }
-
-        }
-        is_filter = ret && is_filter;
-        ret.then(||{ or_or_or += 1});
-    }
-
-    if !matches.and_and_and && or_or_or.gt(&0) {
-        is_filter = true
-    }else if is_filter && or_or_or == 0 {
-        is_filter = false
-    }
-    is_filter
-}
-
-/*
-pub fn is_filter(resp: &HttpResp, matches: &FilterRules) -> bool {
-    let html = &resp.html;
-    let html = String::from_utf8(html.to_vec());
-    if html.is_err() {
-        return true;
-    }
-
-    let html = html.unwrap();
-    let mut is_filter = true;
-    // status code match
-    /*
-    if matches.status_code.contains(&resp.status) {
-        is_match = true
-    } else {
-        return None
-    }
-     */
-    // regex match
-    if let Some(regex) = &matches.regex {
-        is_filter = regex.is_match(&html) && is_filter;
-    }
-    // line match
-    if let Some(n) = &matches.line_num {
-        is_filter = html.lines().count().eq(n) && is_filter
-    }
-
-    // response size
-    if let Some(n) = &matches.resp_size {
-        is_filter = html.len().eq(n) && is_filter
-    }
-
-    is_filter
-}
-
- */
-
 pub fn bytes_to_gb(bytes: u64) -> f64 {
     let gb = bytes as f64 / (1024.0 * 1024.0 * 1024.0);
     gb
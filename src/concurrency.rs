@@ -0,0 +1,135 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::data_type::Stats;
+
+// timeouts and io errors in a window above this halve the concurrency
+pub const FAILURE_RATIO_HIGH: f64 = 0.05;
+// below this the concurrency grows by ADDITIVE_STEP
+pub const FAILURE_RATIO_LOW: f64 = 0.01;
+pub const ADDITIVE_STEP: usize = 10;
+// a window is at least this many requests, and at least the current limit
+const MIN_WINDOW: usize = 50;
+
+/// The failures the limit goes down on, the network or the target doesn't keep up.
+/// DNS, TLS and unclassified errors (IOErr) say nothing about load.
+pub fn is_overload(stat: &Stats) -> bool {
+    matches!(stat, Stats::TimeOut | Stats::ConnRefused | Stats::ConnReset)
+}
+
+#[derive(Debug)]
+struct Window {
+    limit: usize,
+    // permits to drop when they come back, the limit went down
+    debt: usize,
+    requests: usize,
+    failures: usize,
+}
+
+/// In-flight request limit adjusted AIMD-style from the timeout and io error ratio.
+/// The windows follow each other, the counts start over after each decision.
+#[derive(Debug)]
+pub struct AdaptiveConcurrency {
+    semaphore: Arc<Semaphore>,
+    min: usize,
+    max: usize,
+    window: Mutex<Window>,
+}
+
+/// Held while a request is in flight
+pub struct Slot {
+    permit: Option<OwnedSemaphorePermit>,
+    owner: Arc<AdaptiveConcurrency>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut window = self.owner.window.lock().unwrap();
+        if let Some(permit) = self.permit.take() {
+            if window.debt > 0 {
+                window.debt -= 1;
+                permit.forget();
+            }
+        }
+    }
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(start: usize, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        let limit = start.clamp(min, max);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            min,
+            max,
+            window: Mutex::new(Window {
+                limit,
+                debt: 0,
+                requests: 0,
+                failures: 0,
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.window.lock().unwrap().limit
+    }
+
+    pub async fn acquire(self: &Arc<Self>) -> Slot {
+        // never closed
+        let permit = self.semaphore.clone().acquire_owned().await.ok();
+        Slot {
+            permit,
+            owner: self.clone(),
+        }
+    }
+
+    /// Count one request, `failed` for a timeout or io error.
+    /// Returns the new limit when the window closed with a change.
+    pub fn record(&self, failed: bool) -> Option<usize> {
+        let mut window = self.window.lock().unwrap();
+        window.requests += 1;
+        if failed {
+            window.failures += 1;
+        }
+        if window.requests < window.limit.max(MIN_WINDOW) {
+            return None;
+        }
+        let ratio = window.failures as f64 / window.requests as f64;
+        let (requests, failures) = (window.requests, window.failures);
+        window.requests = 0;
+        window.failures = 0;
+        let old = window.limit;
+        let new = if ratio > FAILURE_RATIO_HIGH {
+            (old / 2).max(self.min)
+        } else if ratio < FAILURE_RATIO_LOW {
+            (old + ADDITIVE_STEP).min(self.max)
+        } else {
+            old
+        };
+        if new == old {
+            return None;
+        }
+        if new > old {
+            // pay the debt first, the rest are new permits
+            let paid = window.debt.min(new - old);
+            window.debt -= paid;
+            self.semaphore.add_permits(new - old - paid);
+        } else {
+            window.debt += old - new;
+            // idle permits go right away, the others when their request is done
+            while window.debt > 0 {
+                match self.semaphore.try_acquire() {
+                    Ok(permit) => permit.forget(),
+                    Err(_) => break,
+                }
+                window.debt -= 1;
+            }
+        }
+        window.limit = new;
+        log::info!("[concurrency] {old} -> {new}, timeouts and io errors: {failures}/{requests}");
+        Some(new)
+    }
+}
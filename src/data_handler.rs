@@ -1,138 +1,99 @@
-use std::fs::OpenOptions;
-use std::io::{BufRead, Write};
+use std::io::BufRead;
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::Ordering;
 use std::sync::mpsc::Sender;
 use std::thread;
 use std::thread::JoinHandle;
-use std::time::Duration;
 use console::Style;
+use regex::Regex;
 use script::ScriptEngine;
+use tokio::sync::mpsc::{Receiver, Sender as EventSender};
 
-use crate::{G_LOOP_BREAK, G_RESPONSE};
-use crate::common::{is_filter, is_match};
-use crate::data_type::{FilterRules, Matches, ScanMode, ScriptOpt, Stats};
-use crate::error::stats_inc;
-
-#[derive(Default)]
-struct PrintData {
-    status: u16,
-    ipv4: String,
-    size: usize,
-    lines: usize,
-    duration: u128,
-    url: String,
-    script_output: String,
-}
-
-struct MsgSender {
-    sender: Sender<String>,
-    msg: String,
-    print_opt: Option<PrintData>,
-}
-
-impl MsgSender {
-    pub fn new(sender: Sender<String>)-> Self {
-        Self {
-            sender,
-            msg: "".to_string(),
-            print_opt: None,
-        }
-    }
-    pub fn set_msg(&mut self, msg: String)-> &Self {
-        self.msg = msg;
-        self
-    }
-
-    pub fn set_script_output(&mut self, msg: String)-> &Self {
-        if let Some( opt) = &mut self.print_opt {
-            opt.script_output = msg;
-        }
-        self
-    }
-
-    pub fn debug(&mut self, data: PrintData) -> &Self {
-        self.print_opt = Some(data);
-        self
-    }
-
-    pub fn send_color(&self, color: Style)-> &Self {
-        if let Some(debug) = &self.print_opt {
-            let mut output = format!("[Status: {}, Size: {}, Lines: {}, Duration: {}ms, IP: {}]\n\t* {}",
-                                           debug.status, debug.size, debug.lines, debug.duration, debug.ipv4, debug.url);
-            if !debug.script_output.is_empty() {
-                output += &format!("\toutput: {}", debug.script_output)
-            }
-            //panic!("{}", output);
-            self.sender.send(color.apply_to(output).to_string()).unwrap();
-            return self
-        }
-        if !self.msg.is_empty() {
-            self.sender.send(color.apply_to(&self.msg).to_string()).unwrap();
-        }
-        self
-    }
-
-    pub fn send(&self) {
-        if !self.msg.is_empty() {
-            self.sender.send(self.msg.clone()).unwrap();
-        }
-    }
-}
+use crate::cluster::{Clusters, MAX_CLUSTERS, Membership, simhash};
+use crate::data_type::{FilterRules, Fingerprint, HttpResp, ScanEvent, ScanHit, ScanMode, ScanStats, ScriptOpt, Stats};
+use crate::expr::{Expr, ResponseView};
+use crate::extract::extract;
+use crate::html::title;
+use crate::sink::{ResultSink, TerminalSink};
 
 pub struct ListenData {
     handles: Vec<JoinHandle<()>>,
     print_sender: Sender<String>,
     mode: ScanMode,
+    terminal: bool,
+    stats: Arc<ScanStats>,
+    events: Option<EventSender<ScanEvent>>,
     script: Option<ScriptOpt>,
-    result_outfile: Option<String>
+    clusters: Option<Arc<Mutex<Clusters>>>,
+    extract: Vec<Regex>,
+    sinks: Vec<Box<dyn ResultSink>>,
 }
 
 impl ListenData {
-    pub fn new(print_sender: Sender<String>, mode: ScanMode) -> Self {
+    pub fn new(print_sender: Sender<String>, mode: ScanMode, stats: Arc<ScanStats>) -> Self {
         Self {
             handles: vec![],
             print_sender,
             mode,
+            terminal: true,
+            stats,
+            events: None,
             script: None,
-            result_outfile: None,
+            clusters: None,
+            extract: vec![],
+            sinks: vec![],
         }
     }
 
+    pub fn set_terminal(&mut self, terminal: bool) {
+        self.terminal = terminal;
+    }
+
+    // hits are also sent as scan events
+    pub fn use_events(&mut self, events: EventSender<ScanEvent>) {
+        self.events = Some(events);
+    }
+
     pub fn use_script(&mut self, script: ScriptOpt) {
         self.script = Some(script);
     }
 
-    pub fn save_as(&mut self, outfile: &str) {
-        self.result_outfile = Some(outfile.to_string())
+    // near-duplicate hits past `max_members` per cluster are dropped
+    pub fn use_clusters(&mut self, max_members: usize) {
+        self.clusters = Some(Arc::new(Mutex::new(Clusters::new(max_members, MAX_CLUSTERS))));
     }
 
-    pub fn handler(&mut self, custom_matches: Option<Matches>, custom_filters: Option<FilterRules>) {
-        //jobs
-        let file = match &self.result_outfile {
-            Some(p) => {
-                let  file = OpenOptions::new()
-                    .create(true)
-                    .write(true)
-                    .open(p)
-                    .unwrap();
-                Some(file)
-            }
-            _ => {
-                None
-            }
-        };
-
-        let outfile = Arc::new(Mutex::new(file));
-        for _ in 0..4 {
-            let response_cloned = G_RESPONSE.clone();
-            let loop_break_cloned = G_LOOP_BREAK.clone();
+    pub fn use_extract(&mut self, regexes: Vec<Regex>) {
+        self.extract = regexes;
+    }
+
+    pub fn clusters(&self) -> Option<Arc<Mutex<Clusters>>> {
+        self.clusters.clone()
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn ResultSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn handler(&mut self, receiver: Receiver<HttpResp>, workers: usize,
+                   custom_matches: Option<Expr>, custom_filters: Option<FilterRules>) {
+        let titles = self.sinks.iter().any(|sink| sink.wants_title());
+        // shared by the workers, a hit is written to every sink before the next one
+        let sinks = Arc::new(Mutex::new(std::mem::take(&mut self.sinks)));
+        // workers take turns on the receiver, it returns None once the scanner is done
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers {
+            let receiver = receiver.clone();
             let custom_matches = custom_matches.clone();
             let custom_filters = custom_filters.clone();
-            let outfile = outfile.clone();
+            let sinks = sinks.clone();
             let print_sender = self.print_sender.clone();
-            let scan_mode = self.mode.clone();
+            // nothing goes to the terminal when it is off
+            let scan_mode = if self.terminal { self.mode.clone() } else { ScanMode::Silent };
+            let stats = self.stats.clone();
+            let events = self.events.clone();
             let script_opt = self.script.clone();
+            let clusters = self.clusters.clone();
+            let regexes = self.extract.clone();
             let handle = thread::spawn(move || {
                 let mut script_ctx: Option<ScriptEngine> = None;
                 if let Some(opt) = script_opt {
@@ -144,96 +105,107 @@ impl ListenData {
                         Err(e) => log::error!("{e}")
                     }
                 }
-                let mut msg_sender = MsgSender::new(print_sender);
                 loop {
-                    let resp;
-                    {
-                        let mut read_guard = response_cloned.write().unwrap();
-
-                        if read_guard.queue.is_empty()
-                            && !loop_break_cloned.load(Ordering::SeqCst)
-                        {
-                            break;
-                        }
-                        resp = read_guard.dequeue();
-                    }
-                    if resp.is_none() {
-                        thread::sleep(Duration::from_millis(1));
-                        continue;
-                    }
-
-                    let resp = resp.unwrap();
-                    let url = resp.url.to_string();
+                    let resp = receiver.lock().unwrap().blocking_recv();
+                    let resp = match resp {
+                        Some(r) => r,
+                        None => break,
+                    };
                     let html = String::from_utf8_lossy(resp.html.as_slice());
-
-                    if scan_mode == ScanMode::Debug {
-                        let mut print_data = PrintData::default();
-                        let mut ip = String::from("None");
-                        if let Some(s) = resp.remote_addr {
-                            ip = s.ip().to_string();
-                        }
-                        print_data.ipv4 = ip;
-                        print_data.status = resp.status;
-                        print_data.size = resp.html.len();
-                        print_data.lines = resp.html.lines().count();
-                        print_data.duration = resp.duration.as_millis();
-                        print_data.url = url.clone();
-                        msg_sender.debug(print_data);
-                    }
+                    let hit = |script_output: String, extracted: Vec<(String, String)>| ScanHit {
+                        payload: resp.payload.clone(),
+                        url: resp.url.clone(),
+                        status: resp.status,
+                        size: resp.html.len(),
+                        lines: resp.html.lines().count(),
+                        words: html.split_whitespace().count(),
+                        ttfb: resp.ttfb,
+                        duration: resp.duration,
+                        remote_addr: resp.remote_addr,
+                        content_type: resp.content_type.clone(),
+                        location: resp.location.clone(),
+                        title: if titles { title(&resp.html, resp.content_type.as_deref()) } else { None },
+                        server: resp.server.clone(),
+                        extracted,
+                        script_output,
+                        body: resp.html.clone(),
+                    };
+
+                    let view = ResponseView::new(resp.status, &resp.url, &html)
+                        .with_headers(&resp.headers)
+                        .with_content_type(resp.content_type.as_deref().unwrap_or_default())
+                        .with_timing(resp.ttfb.as_millis() as u64, resp.duration.as_millis() as u64);
                     // filter response body
                     if let Some(filters) = &custom_filters {
-                        if is_filter(&html, filters) {
-                            if scan_mode == ScanMode::Debug {
-                                //msg_sender.send_color(Style::new().red().bold());
-                            }
+                        if filters.expr.as_ref().map(|expr| expr.eval(&view)) == Some(true) {
                             continue
                         }
+                        if !filters.fingerprints.is_empty() {
+                            let shape = Fingerprint::of(resp.status, &resp.html);
+                            if filters.fingerprints.iter().any(|f| f.matches(&shape)) {
+                                continue
+                            }
+                        }
                     }
                     // match response body
                     if let Some(matches) = &custom_matches {
-                        if !is_match(&html, matches) {
+                        if !matches.eval(&view) {
                             if scan_mode == ScanMode::Debug {
-                                msg_sender.send_color(Style::new().red().bold());
+                                let msg = Style::new().red().bold().apply_to(TerminalSink::detail(&hit(String::new(), vec![]), &[]));
+                                let _ = print_sender.send(msg.to_string());
                             }
                             continue;
                         }
                     }
 
+                    // collapse near-duplicate pages, eg. error pages echoing the path
+                    if let Some(clusters) = &clusters {
+                        let hash = simhash(&resp.html);
+                        let membership = clusters.lock().unwrap().add(resp.status, hash, &resp.url);
+                        match membership {
+                            Membership::Keep => {}
+                            Membership::Overflow { representative, members } => {
+                                stats.inc(&Stats::Clustered);
+                                let msg = format!("[cluster] {members} similar responses to {representative} (status {}), hiding the rest", resp.status);
+                                log::info!("{msg}");
+                                if scan_mode != ScanMode::Silent {
+                                    let _ = print_sender.send(Style::new().dim().apply_to(msg).to_string());
+                                }
+                                continue;
+                            }
+                            Membership::Drop => {
+                                stats.inc(&Stats::Clustered);
+                                continue;
+                            }
+                        }
+                    }
+
+                    let extracted = extract(&regexes, &html);
+
                     // if using script
+                    let mut script_output = String::new();
                     if let Some(engine) = &script_ctx {
                         match engine.run_script(html.parse().unwrap()) {
-                            Ok(script_output) => {
-                                msg_sender.set_script_output(script_output);
+                            Ok(output) => {
+                                script_output = output;
                             }
                             Err(e) => log::error!("{}", e.to_string())
                         }
                     }
 
-                    if scan_mode == ScanMode::Debug {
-                        let color = match &resp.status {
-                            200 => Style::new().green().bold(),
-                            301 => Style::new().blue(),
-                            404 => Style::new().dim().bold(),
-                            403 => Style::new().yellow().bold(),
-                            500 => Style::new().red().bold(),
-                            400 => Style::new().dim().bold(),
-                            401 => Style::new().blue().bold(),
-                            _ => Style::new().cyan().bold(),
-                        };
-                        msg_sender.send_color(color);
-                    } else {
-                        msg_sender.set_msg(url.to_string()).send();
-                    }
+                    stats.inc(&Stats::Hits);
+                    let hit = hit(script_output, extracted);
                     drop(resp);
-                    stats_inc(&Stats::Hits);
-                    let mut file = outfile.lock().unwrap();
-                    if let Some(ref mut file) = *file {
-                            let log_text = url.to_string() + "\n";
-                            let write_str = log_text.to_string();
-                            file.write_all(write_str.as_bytes()).unwrap();
-                            file.flush().unwrap();
+                    for sink in sinks.lock().unwrap().iter_mut() {
+                        if let Err(e) = sink.write(&hit).and_then(|_| sink.flush()) {
+                            log::error!("result write failed. {e}");
                         }
                     }
+                    if let Some(events) = &events {
+                        // the stream may have been dropped by its consumer
+                        let _ = events.blocking_send(ScanEvent::Hit(Box::new(hit)));
+                    }
+                }
             });
             self.handles.push(handle)
         }
@@ -1,37 +1,416 @@
-use std::collections::HashSet;
+use std::fmt;
 use std::net::SocketAddr;
 use std::time::Duration;
+use chashmap::CHashMap;
+use rand::Rng;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sysinfo::System;
+use crate::checkpoint::Checkpoint;
+use crate::error::ErrorClass;
+use crate::common::*;
+use crate::expr::Expr;
+use crate::rawhttp::RequestTemplate;
+use crate::retry::RetryPolicy;
+use crate::sink::{Column, OutputFormat};
+use crate::throttle::ThrottleAction;
+use crate::wordlist::{FuzzMode, Wordlist, WordlistSet};
 
 #[derive(Clone, Debug)]
 pub struct Params {
     pub user_agent: String,
     pub request_timeout: u64,
     pub concurrent_num: usize,
+    // min and max in-flight requests, -c is only the start when set
+    pub auto_concurrency: Option<(usize, usize)>,
+    pub worker_num: usize,
+    pub queue_size: usize,
     pub proxy_server: String,
     pub proxy_user: String,
     pub proxy_pass: String,
     pub follow_redirect: usize,
-    pub wordlist: HashSet<String>,
+    pub wordlist: WordlistSet,
     pub wordlist_len: usize,
-    pub fuzz_url: String,
-    pub result_file: Option<String>,
+    pub request: RequestTemplate,
+    // checkpoints are written to data/ when set
+    pub checkpoint: Option<Checkpoint>,
+    pub resumed: bool,
+    // progress bars and hit lines on the terminal
+    pub terminal: bool,
+    // -o files, every hit is written to each of them
+    pub outputs: Vec<(String, OutputFormat)>,
+    // --title, --server and --location on the terminal lines
+    pub columns: Vec<Column>,
     pub print_state: bool,
     pub request_retries: usize,
+    // which errors are retried and the delay between the attempts
+    pub retry_policy: RetryPolicy,
+    // requests per second, over the whole scan and to each host
+    pub rate: Option<f64>,
+    pub host_rate: Option<f64>,
+    // pause a host answering 429/503, max pauses in a row (0 for no limit) and what
+    // happens past it. None sends the requests regardless
+    pub backoff: Option<(usize, ThrottleAction)>,
     pub script_option: Option<ScriptOpt>,
     pub scan_mode: ScanMode,
     pub no_color: bool,
     pub dns_try: bool,
-    pub custom_matches: Option<Matches>,
+    pub custom_matches: Option<Expr>,
     pub custom_filters: Option<FilterRules>,
+    // probe random paths and filter the soft-404 pages
+    pub auto_calibration: bool,
+    // a baseline per host, for scans where FUZZ is the host
+    pub host_calibration: bool,
+    // similar hits reported per cluster, 0 disables clustering
+    pub cluster_size: usize,
+    // --extract regexes, run on every hit
+    pub extract: Vec<Regex>,
+    // deduplicated extracted values, one per line
+    pub extract_output: Option<String>,
 }
 
-#[derive(Clone)]
-pub struct Options<'a> {
-    pub sys: &'a System,
-    pub params: Params,
+impl Params {
+    pub fn builder() -> ParamsBuilder {
+        ParamsBuilder::default()
+    }
+}
+
+/// Scan configuration for library users, the command line builds `Params` in `parse_args`.
+///
+/// ```no_run
+/// # async fn scan() -> Result<(), String> {
+/// use futures::StreamExt;
+/// use kenshi::{Params, ScanEvent, Scanner};
+///
+/// let params = Params::builder()
+///     .url("https://FUZZ/robots.txt")
+///     .wordlist("domains.txt")
+///     .concurrency(200)
+///     .build()?;
+/// let mut events = Scanner::new(params).run();
+/// while let Some(event) = events.next().await {
+///     if let ScanEvent::Hit(hit) = event {
+///         println!("{} {}", hit.status, hit.url);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ParamsBuilder {
+    request: Option<RequestTemplate>,
+    wordlists: Vec<(String, String)>,
+    mode: Option<FuzzMode>,
+    dedup: bool,
+    user_agent: Option<String>,
+    request_timeout: Option<u64>,
+    concurrent_num: Option<usize>,
+    auto_concurrency: Option<(usize, usize)>,
+    worker_num: Option<usize>,
+    queue_size: Option<usize>,
+    proxy: Option<(String, String, String)>,
+    follow_redirect: usize,
+    request_retries: Option<usize>,
+    retry_on: Option<Vec<ErrorClass>>,
+    retry_delay: Option<Duration>,
+    rate: Option<f64>,
+    host_rate: Option<f64>,
+    max_throttle: Option<usize>,
+    throttle_action: Option<ThrottleAction>,
+    no_backoff: bool,
+    script_option: Option<ScriptOpt>,
+    dns_try: bool,
+    custom_matches: Option<String>,
+    custom_filters: Option<String>,
+    auto_calibration: bool,
+    host_calibration: bool,
+    cluster_size: usize,
+    extract: Vec<String>,
+    extract_output: Option<String>,
+    checkpoint: Option<Checkpoint>,
+    resumed: bool,
+    outputs: Vec<(String, OutputFormat)>,
+}
+
+impl ParamsBuilder {
+    pub fn url(mut self, url: &str) -> Self {
+        self.request = Some(RequestTemplate::from_url(url));
+        self
+    }
+
+    pub fn request(mut self, request: RequestTemplate) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    /// Wordlist for the FUZZ keyword
+    pub fn wordlist(self, path: &str) -> Self {
+        self.wordlist_keyword(path, crate::wordlist::DEFAULT_KEYWORD)
+    }
+
+    pub fn wordlist_keyword(mut self, path: &str, keyword: &str) -> Self {
+        self.wordlists.push((path.to_string(), keyword.to_string()));
+        self
+    }
+
+    pub fn mode(mut self, mode: FuzzMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Skip repeated wordlist lines
+    pub fn dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Request timeout seconds
+    pub fn timeout(mut self, seconds: u64) -> Self {
+        self.request_timeout = Some(seconds);
+        self
+    }
+
+    pub fn concurrency(mut self, n: usize) -> Self {
+        self.concurrent_num = Some(n.max(1));
+        self
+    }
+
+    /// Adjust the concurrency between `min` and `max` from the timeout and io error ratio
+    pub fn auto_concurrency(mut self, min: usize, max: usize) -> Self {
+        self.auto_concurrency = Some((min.max(1), max.max(min)));
+        self
+    }
+
+    pub fn workers(mut self, n: usize) -> Self {
+        self.worker_num = Some(n.max(1));
+        self
+    }
+
+    pub fn queue_size(mut self, n: usize) -> Self {
+        self.queue_size = Some(n.max(1));
+        self
+    }
+
+    pub fn proxy(mut self, server: &str, user: &str, pass: &str) -> Self {
+        self.proxy = Some((server.to_string(), user.to_string(), pass.to_string()));
+        self
+    }
+
+    pub fn follow_redirect(mut self, n: usize) -> Self {
+        self.follow_redirect = n;
+        self
+    }
+
+    pub fn retries(mut self, n: usize) -> Self {
+        self.request_retries = Some(n);
+        self
+    }
+
+    /// Error classes sent again, timeout, refused, reset, body and other by default
+    pub fn retry_on(mut self, classes: Vec<ErrorClass>) -> Self {
+        self.retry_on = Some(classes);
+        self
+    }
+
+    /// First delay between two attempts, doubled each time
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = Some(delay);
+        self
+    }
+
+    /// Requests per second over the whole scan
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = Some(rate);
+        self
+    }
+
+    /// Requests per second to each host
+    pub fn host_rate(mut self, rate: f64) -> Self {
+        self.host_rate = Some(rate);
+        self
+    }
+
+    /// Pauses in a row before a host answering 429/503 is given up on, 0 for no limit
+    pub fn max_throttle(mut self, n: usize) -> Self {
+        self.max_throttle = Some(n);
+        self
+    }
+
+    pub fn throttle_action(mut self, action: ThrottleAction) -> Self {
+        self.throttle_action = Some(action);
+        self
+    }
+
+    /// Treat 429 and 503 as any other response
+    pub fn no_backoff(mut self) -> Self {
+        self.no_backoff = true;
+        self
+    }
+
+    pub fn script(mut self, path: &str) -> Self {
+        self.script_option = Some(ScriptOpt { script_path: path.to_string() });
+        self
+    }
+
+    pub fn dns_try(mut self, enable: bool) -> Self {
+        self.dns_try = enable;
+        self
+    }
+
+    /// Match expression, eg. `status in [200,403] && !body ~ "Not Found"`
+    pub fn matches(mut self, expr: &str) -> Self {
+        self.custom_matches = Some(expr.to_string());
+        self
+    }
+
+    /// Filter expression, matching responses are dropped
+    pub fn filters(mut self, expr: &str) -> Self {
+        self.custom_filters = Some(expr.to_string());
+        self
+    }
+
+    /// Write the hits to a file, the format follows the extension
+    pub fn output(self, path: &str) -> Self {
+        let format = OutputFormat::from_path(path);
+        self.output_as(path, format)
+    }
+
+    pub fn output_as(mut self, path: &str, format: OutputFormat) -> Self {
+        self.outputs.push((path.to_string(), format));
+        self
+    }
+
+    pub fn auto_calibration(mut self, enable: bool) -> Self {
+        self.auto_calibration = enable;
+        self
+    }
+
+    pub fn host_calibration(mut self, enable: bool) -> Self {
+        self.host_calibration = enable;
+        self
+    }
+
+    /// Suppress clusters of similar hits past `max_members`, 0 disables it
+    pub fn cluster(mut self, max_members: usize) -> Self {
+        self.cluster_size = max_members;
+        self
+    }
+
+    /// Regex whose captures are collected from each hit, named groups keep their name
+    pub fn extract(mut self, regex: &str) -> Self {
+        self.extract.push(regex.to_string());
+        self
+    }
+
+    /// Write every extracted value once to a file
+    pub fn extract_output(mut self, path: &str) -> Self {
+        self.extract_output = Some(path.to_string());
+        self
+    }
+
+    pub fn checkpoint(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Go on with an interrupted scan, the wordlists must be the same
+    pub fn resume(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self.resumed = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Params, String> {
+        let request = match self.request {
+            Some(r) => r,
+            None => return Err("fuzz url is empty.".to_string()),
+        };
+        request.validate()?;
+        if self.wordlists.is_empty() {
+            return Err("missing wordlist.".to_string());
+        }
+        let mut lists = vec![];
+        for (path, keyword) in self.wordlists.iter() {
+            let list = Wordlist::from_file(path, keyword, None)
+                .map_err(|e| format!(r#"wordlist "{path}" read failed. {e}"#))?;
+            if !request.contains(keyword) {
+                return Err(format!(r#"not found "{keyword}" str."#));
+            }
+            lists.push(list);
+        }
+        let mut wordlist = WordlistSet::new(lists, self.mode.unwrap_or(FuzzMode::Clusterbomb));
+        if self.dedup {
+            wordlist = wordlist.with_dedup();
+        }
+        let mut checkpoint = self.checkpoint;
+        if let Some(checkpoint) = checkpoint.as_mut() {
+            checkpoint.use_wordlist(&wordlist, self.resumed)?;
+        }
+        let user_agent = match self.user_agent {
+            Some(ua) => ua,
+            None => {
+                let mut rng = rand::thread_rng();
+                COMMON_USER_AGENTS[rng.gen_range(0..COMMON_USER_AGENTS.len() - 1)].to_string()
+            }
+        };
+        let (proxy_server, proxy_user, proxy_pass) = self.proxy.unwrap_or_default();
+        let custom_matches = self.custom_matches.as_deref().map(Expr::parse).transpose()?;
+        let custom_filters = self.custom_filters.as_deref().map(Expr::parse).transpose()?
+            .map(|expr| FilterRules { expr: Some(expr), fingerprints: vec![] });
+        let extract = self.extract.iter()
+            .map(|r| Regex::new(r).map_err(|e| format!("[extract] {e}")))
+            .collect::<Result<Vec<Regex>, String>>()?;
+        Ok(Params {
+            user_agent,
+            request_timeout: self.request_timeout.unwrap_or(G_DEFAULT_REQUEST_TIMEOUT),
+            concurrent_num: self.concurrent_num.unwrap_or(G_DEFAULT_CONCURRENT_NUM),
+            auto_concurrency: self.auto_concurrency,
+            worker_num: self.worker_num.unwrap_or(G_DEFAULT_WORKER_NUM),
+            queue_size: self.queue_size.unwrap_or(G_DEFAULT_QUEUE_SIZE),
+            proxy_server,
+            proxy_user,
+            proxy_pass,
+            follow_redirect: self.follow_redirect,
+            wordlist_len: wordlist.len(),
+            wordlist,
+            request,
+            checkpoint,
+            resumed: self.resumed,
+            terminal: false,
+            outputs: self.outputs,
+            columns: vec![],
+            print_state: false,
+            request_retries: self.request_retries.unwrap_or(1),
+            retry_policy: {
+                let default = RetryPolicy::default();
+                RetryPolicy {
+                    classes: self.retry_on.unwrap_or(default.classes),
+                    base_delay: self.retry_delay.unwrap_or(default.base_delay),
+                }
+            },
+            rate: self.rate.filter(|r| *r > 0.0),
+            host_rate: self.host_rate.filter(|r| *r > 0.0),
+            backoff: (!self.no_backoff).then(|| (
+                self.max_throttle.unwrap_or(G_DEFAULT_MAX_THROTTLE),
+                self.throttle_action.unwrap_or(ThrottleAction::Drop),
+            )),
+            script_option: self.script_option,
+            scan_mode: ScanMode::Silent,
+            no_color: false,
+            dns_try: self.dns_try,
+            custom_matches,
+            custom_filters,
+            auto_calibration: self.auto_calibration,
+            host_calibration: self.host_calibration,
+            cluster_size: self.cluster_size,
+            extract,
+            extract_output: self.extract_output,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -44,31 +423,37 @@ pub struct MatchResult {
     pub url: String,
 }
 
-#[derive(Debug, Clone)]
-pub struct Matches {
-    pub regex: Option<Regex>,
-    pub status_code: Option<HashSet<u16>>,
-    pub line_num: Option<usize>,
-    pub resp_size: Option<usize>,
-    pub and_and_and: bool,
+#[derive(Debug, Clone, Default)]
+pub struct FilterRules {
+    pub expr: Option<Expr>,
+    // soft-404 pages found by auto-calibration, each one filters on its own
+    pub fingerprints: Vec<Fingerprint>,
 }
 
-#[derive(Debug, Clone)]
-pub struct FilterRules {
-    pub regex: Option<Regex>,
-    pub status_code: Option<HashSet<u16>>,
-    pub line_num: Option<HashSet<usize>>,
-    pub resp_size: Option<Vec<String>>,
-    pub and_and_and: bool,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    pub status: u16,
+    pub size: Option<usize>,
+    pub lines: Option<usize>,
+    pub words: Option<usize>,
 }
 
 #[derive(Clone, Debug)]
 pub struct HttpResp {
+    pub payload: String,
     pub url: String,
     pub status: u16,
     pub html: Vec<u8>,
+    // until the status line and headers are in
+    pub ttfb: Duration,
+    // until the whole body is read
     pub duration: Duration,
     pub remote_addr: Option<SocketAddr>,
+    pub content_type: Option<String>,
+    // redirect target, when redirects are not followed
+    pub location: Option<String>,
+    pub server: Option<String>,
+    pub headers: Vec<(String, String)>,
 }
 
 #[derive(PartialEq, Clone, Debug)]
@@ -78,17 +463,17 @@ pub enum ScanMode {
     Silent,
 }
 
-impl ToString for ScanMode {
-    fn to_string(&self) -> String {
-        match self {
-            ScanMode::Debug => String::from("debug"),
-            ScanMode::Stats => String::from("detail"),
-            ScanMode::Silent => String::from("silent"),
-        }
+impl fmt::Display for ScanMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScanMode::Debug => "debug",
+            ScanMode::Stats => "detail",
+            ScanMode::Silent => "silent",
+        })
     }
 }
 
-#[derive(Hash, PartialEq, Debug)]
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Stats {
     IOErr,
     Done,
@@ -104,4 +489,160 @@ pub enum Stats {
     C403,
     C401,
     C000,
-}
\ No newline at end of file
+    // hits dropped by similarity clustering
+    Clustered,
+    // 429, and 503 with Retry-After, responses. The requests were sent again
+    Throttled,
+    // payloads skipped, their host kept throttling
+    Dropped,
+    // retries spent, by error class
+    RetryTimeout,
+    RetryReset,
+    RetryDns,
+    RetryTls,
+    RetryBody,
+    RetryOther,
+    // failed requests beside TimeOut, DNSErr and the generic IOErr
+    ConnRefused,
+    ConnReset,
+    TlsErr,
+    ProxyErr,
+    RedirectErr,
+    // the body couldn't be read or decompressed
+    DecodeErr,
+    // calibration requests of --ac and --ach, beside the payloads
+    Probes,
+    // with the other retry counters it would shift the saved checkpoints
+    RetryRefused,
+}
+
+impl Stats {
+    pub fn all() -> Vec<Stats> {
+        vec![
+            Stats::IOErr,
+            Stats::Done,
+            Stats::Hits,
+            Stats::DNSErr,
+            Stats::TimeOut,
+            Stats::C404,
+            Stats::C502,
+            Stats::C500,
+            Stats::C200,
+            Stats::C301,
+            Stats::C302,
+            Stats::C403,
+            Stats::C401,
+            Stats::C000,
+            Stats::Clustered,
+            Stats::Throttled,
+            Stats::Dropped,
+            Stats::RetryTimeout,
+            Stats::RetryReset,
+            Stats::RetryDns,
+            Stats::RetryTls,
+            Stats::RetryBody,
+            Stats::RetryOther,
+            Stats::ConnRefused,
+            Stats::ConnReset,
+            Stats::TlsErr,
+            Stats::ProxyErr,
+            Stats::RedirectErr,
+            Stats::DecodeErr,
+            Stats::Probes,
+            Stats::RetryRefused,
+        ]
+    }
+}
+
+/// Counters of one scan, two scans in a process don't share them.
+#[derive(Debug)]
+pub struct ScanStats {
+    counters: CHashMap<Stats, u32>,
+}
+
+impl Default for ScanStats {
+    fn default() -> Self {
+        let counters = CHashMap::new();
+        for stat in Stats::all() {
+            counters.insert(stat, 0);
+        }
+        Self {
+            counters,
+        }
+    }
+}
+
+impl ScanStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inc(&self, stat: &Stats) {
+        if let Some(mut value) = self.counters.get_mut(stat) {
+            *value += 1;
+        }
+    }
+
+    pub fn get(&self, stat: &Stats) -> u32 {
+        self.counters.get(stat).map(|v| *v).unwrap_or(0)
+    }
+
+    pub fn set(&self, stat: &Stats, n: u32) {
+        if let Some(mut value) = self.counters.get_mut(stat) {
+            *value = n;
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<(Stats, u32)> {
+        Stats::all().into_iter().map(|s| (s, self.get(&s))).collect()
+    }
+}
+
+/// A response that passed the filters and matchers
+#[derive(Clone, Debug)]
+pub struct ScanHit {
+    pub payload: String,
+    pub url: String,
+    pub status: u16,
+    pub size: usize,
+    pub lines: usize,
+    pub words: usize,
+    pub ttfb: Duration,
+    pub duration: Duration,
+    pub remote_addr: Option<SocketAddr>,
+    pub content_type: Option<String>,
+    pub location: Option<String>,
+    // only parsed when a sink shows it, see ResultSink::wants_title
+    pub title: Option<String>,
+    pub server: Option<String>,
+    // --extract captures, (name, value)
+    pub extracted: Vec<(String, String)>,
+    pub script_output: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScanSummary {
+    pub stats: Vec<(Stats, u32)>,
+    pub elapsed: Duration,
+    // stopped before the wordlist was exhausted
+    pub interrupted: bool,
+    // suppressed similarity clusters, representative url and member count
+    pub clusters: Vec<(String, usize)>,
+    // hosts given up on, they kept throttling
+    pub dropped_hosts: Vec<String>,
+}
+
+impl ScanSummary {
+    pub fn get(&self, stat: &Stats) -> u32 {
+        self.stats.iter().find(|(s, _)| s == stat).map_or(0, |(_, n)| *n)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ScanEvent {
+    // boxed, a hit is much larger than the other events
+    Hit(Box<ScanHit>),
+    Error(String),
+    Finished(ScanSummary),
+}
@@ -1,44 +1,152 @@
 use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
 
 use hyper::Error as hyper_error;
 use trust_dns_resolver::error::ResolveError;
 
-use crate::data_type::Stats;
-use crate::G_STATS;
+use crate::data_type::{ScanStats, Stats};
 
-pub fn stats_inc(stat: &Stats) {
-    let mut value = G_STATS.get_mut(stat).unwrap();
-    *value += 1;
+/// Why a request failed, retries are decided on it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorClass {
+    Timeout,
+    // nothing listens, or the backlog is full
+    Refused,
+    // connection reset or closed mid-request
+    Reset,
+    Dns,
+    Tls,
+    // the headers came, reading the body failed
+    Body,
+    Other,
 }
 
-pub fn stats_err_inc(e: &reqwest::Error) {
+impl ErrorClass {
+    pub fn all() -> Vec<ErrorClass> {
+        vec![ErrorClass::Timeout, ErrorClass::Refused, ErrorClass::Reset, ErrorClass::Dns, ErrorClass::Tls,
+             ErrorClass::Body, ErrorClass::Other]
+    }
+
+    /// Counter of the retries spent on this class
+    pub fn retry_stat(&self) -> Stats {
+        match self {
+            ErrorClass::Timeout => Stats::RetryTimeout,
+            ErrorClass::Refused => Stats::RetryRefused,
+            ErrorClass::Reset => Stats::RetryReset,
+            ErrorClass::Dns => Stats::RetryDns,
+            ErrorClass::Tls => Stats::RetryTls,
+            ErrorClass::Body => Stats::RetryBody,
+            ErrorClass::Other => Stats::RetryOther,
+        }
+    }
+}
+
+impl FromStr for ErrorClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "timeout" => Ok(ErrorClass::Timeout),
+            "refused" => Ok(ErrorClass::Refused),
+            "reset" => Ok(ErrorClass::Reset),
+            "dns" => Ok(ErrorClass::Dns),
+            "tls" => Ok(ErrorClass::Tls),
+            "body" => Ok(ErrorClass::Body),
+            "other" => Ok(ErrorClass::Other),
+            _ => Err(format!("[retry] unknown error class \"{s}\". Available classes: timeout, refused, reset, dns, tls, body, other")),
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Refused => "refused",
+            ErrorClass::Reset => "reset",
+            ErrorClass::Dns => "dns",
+            ErrorClass::Tls => "tls",
+            ErrorClass::Body => "body",
+            ErrorClass::Other => "other",
+        })
+    }
+}
+
+// the error and its sources. io::Error hides the error it wraps from source()
+fn chain<'a>(e: &'a (dyn Error + 'static)) -> Vec<&'a (dyn Error + 'static)> {
+    let mut errors = vec![];
+    let mut next = Some(e);
+    while let Some(e) = next {
+        errors.push(e);
+        next = match e.downcast_ref::<io::Error>().and_then(|e| e.get_ref()) {
+            Some(inner) => Some(inner as &(dyn Error + 'static)),
+            None => e.source(),
+        };
+    }
+    errors
+}
+
+pub fn classify(e: &reqwest::Error) -> ErrorClass {
     if e.is_timeout() {
-        stats_inc(&Stats::TimeOut);
-        return;
-    }
-
-    if let Some(hyper_err) = e.source() {
-        if let Some(connect_err) = &hyper_err.downcast_ref::<hyper_error>() {
-            if let Some(e) = connect_err.source() {
-                if let Some(e) = e.source() {
-                    let resolve_err = e.downcast_ref::<ResolveError>();
-                    if let Some(resolve_err) = resolve_err {
-                        if let trust_dns_resolver::error::ResolveErrorKind::NoRecordsFound { .. } = resolve_err.kind() {
-                            stats_inc(&Stats::DNSErr);
-                            return;
-                        }
-                    }
-                }
+        return ErrorClass::Timeout;
+    }
+    for e in chain(e) {
+        // reqwest resolves with hickory, --dns-try with trust-dns
+        if e.is::<hickory_resolver::error::ResolveError>() || e.is::<ResolveError>() {
+            return ErrorClass::Dns;
+        }
+        if e.is::<rustls::Error>() {
+            return ErrorClass::Tls;
+        }
+        if let Some(e) = e.downcast_ref::<io::Error>() {
+            match e.kind() {
+                io::ErrorKind::TimedOut => return ErrorClass::Timeout,
+                io::ErrorKind::ConnectionRefused => return ErrorClass::Refused,
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof => return ErrorClass::Reset,
+                _ => {}
             }
         }
+        if let Some(e) = e.downcast_ref::<hyper_error>() {
+            if e.is_incomplete_message() {
+                return ErrorClass::Reset;
+            }
+        }
+    }
+    if e.is_body() || e.is_decode() {
+        return ErrorClass::Body;
     }
+    ErrorClass::Other
+}
 
-    if e.is_request() {
-        stats_inc(&Stats::IOErr);
+/// Counter of a failed request, from its class. `proxied` when the requests
+/// go through --proxy, connection failures are the proxy's then.
+pub fn error_stat(e: &reqwest::Error, proxied: bool) -> Stats {
+    match classify(e) {
+        ErrorClass::Timeout => Stats::TimeOut,
+        ErrorClass::Tls => Stats::TlsErr,
+        _ if proxied && e.is_connect() => Stats::ProxyErr,
+        ErrorClass::Refused => Stats::ConnRefused,
+        ErrorClass::Reset => Stats::ConnReset,
+        ErrorClass::Dns => Stats::DNSErr,
+        ErrorClass::Body => Stats::DecodeErr,
+        ErrorClass::Other if e.is_redirect() => Stats::RedirectErr,
+        ErrorClass::Other => Stats::IOErr,
     }
 }
 
-pub fn stats_code_inc(stat: &u16) {
+/// Count the error, returns the counter it went to
+pub fn stats_err_inc(stats: &ScanStats, e: &reqwest::Error, proxied: bool) -> Stats {
+    let stat = error_stat(e, proxied);
+    stats.inc(&stat);
+    stat
+}
+
+pub fn stats_code_inc(stats: &ScanStats, stat: &u16) {
     let code = match stat {
         502 => &Stats::C502,
         500 => &Stats::C500,
@@ -50,5 +158,5 @@ pub fn stats_code_inc(stat: &u16) {
         302 => &Stats::C302,
         _ => &Stats::C000,
     };
-    stats_inc(code)
+    stats.inc(code)
 }
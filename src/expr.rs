@@ -0,0 +1,609 @@
+use std::cell::Cell;
+use std::fmt;
+use std::str::FromStr;
+
+use once_cell::unsync::OnceCell;
+use regex::Regex;
+
+/// The response as seen by match/filter expressions,
+/// line and word counts are only computed when an expression asks for them.
+pub struct ResponseView<'a> {
+    pub status: u16,
+    pub url: &'a str,
+    pub body: &'a str,
+    pub headers: &'a [(String, String)],
+    pub content_type: &'a str,
+    // milliseconds to the first response byte and to the end of the body
+    pub ttfb: u64,
+    pub time: u64,
+    lines: Cell<Option<usize>>,
+    words: Cell<Option<usize>>,
+    // "Name: value" lines, what header regexes run on
+    headers_text: OnceCell<String>,
+}
+
+impl<'a> ResponseView<'a> {
+    pub fn new(status: u16, url: &'a str, body: &'a str) -> Self {
+        Self {
+            status,
+            url,
+            body,
+            headers: &[],
+            content_type: "",
+            ttfb: 0,
+            time: 0,
+            lines: Cell::new(None),
+            words: Cell::new(None),
+            headers_text: OnceCell::new(),
+        }
+    }
+
+    pub fn with_headers(mut self, headers: &'a [(String, String)]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn with_content_type(mut self, content_type: &'a str) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    pub fn with_timing(mut self, ttfb: u64, time: u64) -> Self {
+        self.ttfb = ttfb;
+        self.time = time;
+        self
+    }
+
+    fn headers_text(&self) -> &str {
+        self.headers_text.get_or_init(|| {
+            self.headers.iter()
+                .map(|(name, value)| format!("{name}: {value}"))
+                .collect::<Vec<String>>()
+                .join("\n")
+        })
+    }
+
+    fn lines(&self) -> usize {
+        let n = self.lines.get().unwrap_or_else(|| self.body.lines().count());
+        self.lines.set(Some(n));
+        n
+    }
+
+    fn words(&self) -> usize {
+        let n = self.words.get().unwrap_or_else(|| self.body.split_whitespace().count());
+        self.words.set(Some(n));
+        n
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Status,
+    Size,
+    Lines,
+    Words,
+    Body,
+    Url,
+    Headers,
+    ContentType,
+    Ttfb,
+    Time,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "status" => Some(Field::Status),
+            "size" => Some(Field::Size),
+            "lines" => Some(Field::Lines),
+            "words" => Some(Field::Words),
+            "body" => Some(Field::Body),
+            "url" => Some(Field::Url),
+            "headers" => Some(Field::Headers),
+            "content_type" => Some(Field::ContentType),
+            "ttfb" => Some(Field::Ttfb),
+            "time" => Some(Field::Time),
+            _ => None,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        !matches!(self, Field::Body | Field::Url | Field::Headers | Field::ContentType)
+    }
+
+    fn num(&self, resp: &ResponseView) -> u64 {
+        match self {
+            Field::Status => resp.status as u64,
+            Field::Size => resp.body.len() as u64,
+            Field::Lines => resp.lines() as u64,
+            Field::Words => resp.words() as u64,
+            Field::Ttfb => resp.ttfb,
+            Field::Time => resp.time,
+            _ => 0,
+        }
+    }
+
+    fn text<'a>(&self, resp: &'a ResponseView) -> &'a str {
+        match self {
+            Field::Body => resp.body,
+            Field::Url => resp.url,
+            Field::Headers => resp.headers_text(),
+            Field::ContentType => resp.content_type,
+            _ => "",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NumOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl NumOp {
+    fn apply(&self, a: u64, b: u64) -> bool {
+        match self {
+            NumOp::Eq => a == b,
+            NumOp::Ne => a != b,
+            NumOp::Gt => a > b,
+            NumOp::Ge => a >= b,
+            NumOp::Lt => a < b,
+            NumOp::Le => a <= b,
+        }
+    }
+}
+
+/// One numeric rule, eg. "1234", ">1000", "<=10", "10-50" (both ends included)
+/// or "10..50" (end excluded). The numeric options and `in [..]` lists share them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumRule {
+    Eq(u64),
+    Gt(u64),
+    Ge(u64),
+    Lt(u64),
+    Le(u64),
+    Range(u64, u64),
+    RangeExclusive(u64, u64),
+}
+
+impl NumRule {
+    pub fn matches(&self, v: u64) -> bool {
+        match *self {
+            NumRule::Eq(n) => v == n,
+            NumRule::Gt(n) => v > n,
+            NumRule::Ge(n) => v >= n,
+            NumRule::Lt(n) => v < n,
+            NumRule::Le(n) => v <= n,
+            NumRule::Range(start, end) => (start..=end).contains(&v),
+            NumRule::RangeExclusive(start, end) => (start..end).contains(&v),
+        }
+    }
+
+    /// Comma separated rules, any of them may match. eg. "<100,>1000,10-50,1234"
+    pub fn parse_list(s: &str) -> Result<Vec<NumRule>, String> {
+        s.split(',').map(|rule| rule.trim().parse()).collect()
+    }
+}
+
+impl FromStr for NumRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid rule \"{s}\", expected eg. 1234, >1000, <=10, 10-50 or 10..50");
+        let tokens = tokenize(s).map_err(|_| invalid())?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let rule = parser.rule().map_err(|_| invalid())?;
+        if parser.peek().is_some() {
+            return Err(invalid());
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for NumRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumRule::Eq(n) => write!(f, "{n}"),
+            NumRule::Gt(n) => write!(f, ">{n}"),
+            NumRule::Ge(n) => write!(f, ">={n}"),
+            NumRule::Lt(n) => write!(f, "<{n}"),
+            NumRule::Le(n) => write!(f, "<={n}"),
+            NumRule::Range(start, end) => write!(f, "{start}-{end}"),
+            NumRule::RangeExclusive(start, end) => write!(f, "{start}..{end}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Cond {
+    Num(Field, NumOp, u64),
+    In(Field, Vec<NumRule>),
+    Text(Field, bool, String),
+    Regex(Field, bool, Regex),
+}
+
+impl Cond {
+    fn field(&self) -> Field {
+        match self {
+            Cond::Num(f, _, _) | Cond::In(f, _) | Cond::Text(f, _, _) | Cond::Regex(f, _, _) => *f,
+        }
+    }
+
+    fn eval(&self, resp: &ResponseView) -> bool {
+        match self {
+            Cond::Num(field, op, n) => op.apply(field.num(resp), *n),
+            Cond::In(field, rules) => {
+                let v = field.num(resp);
+                rules.iter().any(|rule| rule.matches(v))
+            }
+            Cond::Text(field, eq, s) => (field.text(resp) == s) == *eq,
+            Cond::Regex(field, eq, regex) => regex.is_match(field.text(resp)) == *eq,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Bool(bool),
+    Cond(Cond),
+}
+
+impl Node {
+    fn eval(&self, resp: &ResponseView) -> bool {
+        match self {
+            Node::And(a, b) => a.eval(resp) && b.eval(resp),
+            Node::Or(a, b) => a.eval(resp) || b.eval(resp),
+            Node::Not(a) => !a.eval(resp),
+            Node::Bool(b) => *b,
+            Node::Cond(c) => c.eval(resp),
+        }
+    }
+
+    // None when the result depends on more than the status code
+    fn eval_status(&self, status: u16) -> Option<bool> {
+        match self {
+            Node::And(a, b) => match (a.eval_status(status), b.eval_status(status)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Node::Or(a, b) => match (a.eval_status(status), b.eval_status(status)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            Node::Not(a) => a.eval_status(status).map(|v| !v),
+            Node::Bool(b) => Some(*b),
+            Node::Cond(c) if c.field() == Field::Status => {
+                Some(c.eval(&ResponseView::new(status, "", "")))
+            }
+            Node::Cond(_) => None,
+        }
+    }
+}
+
+/// A compiled match or filter expression, eg.
+/// `status in [200,403] && size > 1000 && !body ~ "Not Found"`
+#[derive(Clone, Debug)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("[expr] unexpected {token} in \"{source}\""));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    pub fn eval(&self, resp: &ResponseView) -> bool {
+        self.root.eval(resp)
+    }
+
+    /// Decide from the status code alone, so the body is only downloaded when needed
+    pub fn eval_status(&self, status: u16) -> Option<bool> {
+        self.root.eval_status(status)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Num(u64),
+    Str(String),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Dash,
+    DotDot,
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Match,
+    NotMatch,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Token::Ident(s) => return write!(f, "\"{s}\""),
+            Token::Num(n) => return write!(f, "{n}"),
+            Token::Str(s) => return write!(f, "{}", quote(s)),
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::Not => "!",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::Comma => ",",
+            Token::Dash => "-",
+            Token::DotDot => "..",
+            Token::Eq => "==",
+            Token::Ne => "!=",
+            Token::Gt => ">",
+            Token::Ge => ">=",
+            Token::Lt => "<",
+            Token::Le => "<=",
+            Token::Match => "~",
+            Token::NotMatch => "!~",
+        };
+        write!(f, "'{symbol}'")
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            ' ' | '\t' | '\n' => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            ',' => Token::Comma,
+            '-' => Token::Dash,
+            '.' if chars.next_if_eq(&'.').is_some() => Token::DotDot,
+            '~' => Token::Match,
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Eq,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Ne,
+            '!' if chars.next_if_eq(&'~').is_some() => Token::NotMatch,
+            '!' => Token::Not,
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Ge,
+            '>' => Token::Gt,
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Le,
+            '<' => Token::Lt,
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some(c) if c == '"' || c == '\\' => s.push(c),
+                            // regex escapes like \d are kept
+                            Some(c) => {
+                                s.push('\\');
+                                s.push(c);
+                            }
+                            None => return Err(format!("[expr] unterminated string in \"{source}\"")),
+                        },
+                        Some('"') => break,
+                        Some(c) => s.push(c),
+                        None => return Err(format!("[expr] unterminated string in \"{source}\"")),
+                    }
+                }
+                Token::Str(s)
+            }
+            c if c.is_ascii_digit() => {
+                let mut s = c.to_string();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    s.push(d);
+                }
+                Token::Num(s.parse().map_err(|_| format!("[expr] number too large \"{s}\""))?)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut s = c.to_string();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_alphanumeric() || *d == '_') {
+                    s.push(d);
+                }
+                Token::Ident(s)
+            }
+            c => return Err(format!("[expr] unexpected '{c}' in \"{source}\"")),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+// or  := and ('||' and)*
+// and := not ('&&' not)*
+// not := '!' not | '(' or ')' | true | false | field op value
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == expected => Ok(()),
+            Some(t) => Err(format!("[expr] expected {expected}, found {t}")),
+            None => Err(format!("[expr] expected {expected}, found the end")),
+        }
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut node = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut node = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            node = Node::And(Box::new(node), Box::new(self.not()?));
+        }
+        Ok(node)
+    }
+
+    fn not(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Node::Not(Box::new(self.not()?))),
+            Some(Token::LParen) => {
+                let node = self.or()?;
+                self.expect(Token::RParen)?;
+                Ok(node)
+            }
+            Some(Token::Ident(name)) if name == "true" => Ok(Node::Bool(true)),
+            Some(Token::Ident(name)) if name == "false" => Ok(Node::Bool(false)),
+            Some(Token::Ident(name)) => {
+                let field = Field::from_name(&name)
+                    .ok_or_else(|| format!("[expr] unknown field \"{name}\". Available fields: status, size, lines, words, body, url, headers, content_type, ttfb, time"))?;
+                self.cond(field, &name).map(Node::Cond)
+            }
+            Some(t) => Err(format!("[expr] unexpected {t}")),
+            None => Err("[expr] unexpected end of expression".to_string()),
+        }
+    }
+
+    fn cond(&mut self, field: Field, name: &str) -> Result<Cond, String> {
+        let op = self.next();
+        if field.is_numeric() {
+            let op = match op {
+                Some(Token::Eq) => NumOp::Eq,
+                Some(Token::Ne) => NumOp::Ne,
+                Some(Token::Gt) => NumOp::Gt,
+                Some(Token::Ge) => NumOp::Ge,
+                Some(Token::Lt) => NumOp::Lt,
+                Some(Token::Le) => NumOp::Le,
+                Some(Token::Ident(s)) if s == "in" => return Ok(Cond::In(field, self.list()?)),
+                _ => return Err(format!("[expr] {name} expects ==, !=, >, >=, <, <= or in")),
+            };
+            return match self.next() {
+                Some(Token::Num(n)) => Ok(Cond::Num(field, op, n)),
+                _ => Err(format!("[expr] {name} is compared with a number")),
+            };
+        }
+        let (is_regex, eq) = match op {
+            Some(Token::Eq) => (false, true),
+            Some(Token::Ne) => (false, false),
+            Some(Token::Match) => (true, true),
+            Some(Token::NotMatch) => (true, false),
+            _ => return Err(format!("[expr] {name} expects ==, !=, ~ or !~")),
+        };
+        let value = match self.next() {
+            Some(Token::Str(s)) => s,
+            _ => return Err(format!("[expr] {name} is compared with a \"string\"")),
+        };
+        if is_regex {
+            let regex = Regex::new(&value).map_err(|e| format!("[expr] {e}"))?;
+            return Ok(Cond::Regex(field, eq, regex));
+        }
+        Ok(Cond::Text(field, eq, value))
+    }
+
+    // [200, 300-399, >1000]
+    fn list(&mut self) -> Result<Vec<NumRule>, String> {
+        self.expect(Token::LBracket)?;
+        let mut rules = vec![];
+        if self.peek() == Some(&Token::RBracket) {
+            self.next();
+            return Ok(rules);
+        }
+        loop {
+            rules.push(self.rule()?);
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::RBracket) => break,
+                _ => return Err("[expr] expected , or ] in list".to_string()),
+            }
+        }
+        Ok(rules)
+    }
+
+    // 1234, >1000, <=10, 10-50 or 10..50
+    fn rule(&mut self) -> Result<NumRule, String> {
+        let op = match self.peek() {
+            Some(Token::Gt | Token::Ge | Token::Lt | Token::Le) => self.next(),
+            _ => None,
+        };
+        let n = match self.next() {
+            Some(Token::Num(n)) => n,
+            _ => return Err("[expr] lists hold numbers, ranges and comparisons, eg. [200, 300-399, >1000]".to_string()),
+        };
+        let rule = match op {
+            Some(Token::Gt) => NumRule::Gt(n),
+            Some(Token::Ge) => NumRule::Ge(n),
+            Some(Token::Lt) => NumRule::Lt(n),
+            Some(Token::Le) => NumRule::Le(n),
+            _ => match self.peek() {
+                Some(Token::Dash) => {
+                    self.next();
+                    match self.next() {
+                        Some(Token::Num(end)) if end >= n => NumRule::Range(n, end),
+                        _ => return Err(format!("[expr] invalid range starting at {n}")),
+                    }
+                }
+                Some(Token::DotDot) => {
+                    self.next();
+                    match self.next() {
+                        Some(Token::Num(end)) if end > n => NumRule::RangeExclusive(n, end),
+                        _ => return Err(format!("[expr] invalid range starting at {n}")),
+                    }
+                }
+                _ => NumRule::Eq(n),
+            },
+        };
+        Ok(rule)
+    }
+}
+
+/// Quote a string for use in an expression
+pub fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+// a page full of matches would otherwise bloat every output line
+pub const MAX_EXTRACTED_VALUES: usize = 100;
+
+/// Values captured by the --extract regexes, as (name, value). Named groups keep
+/// their name, a regex without named groups gives its whole match as "match".
+/// Duplicates are dropped, the first occurrence keeps its place.
+pub fn extract(regexes: &[Regex], body: &str) -> Vec<(String, String)> {
+    let mut values: Vec<(String, String)> = vec![];
+    let mut push = |name: &str, value: &str| {
+        if values.len() < MAX_EXTRACTED_VALUES && !values.iter().any(|(n, v)| n == name && v == value) {
+            values.push((name.to_string(), value.to_string()));
+        }
+    };
+    for regex in regexes {
+        let names: Vec<&str> = regex.capture_names().flatten().collect();
+        for caps in regex.captures_iter(body) {
+            if names.is_empty() {
+                push("match", &caps[0]);
+                continue;
+            }
+            for name in names.iter() {
+                if let Some(m) = caps.name(name) {
+                    push(name, m.as_str());
+                }
+            }
+        }
+    }
+    values
+}
+
+/// Values grouped by name, in name order
+pub fn group(values: &[(String, String)]) -> BTreeMap<&str, Vec<&str>> {
+    let mut groups: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, value) in values {
+        groups.entry(name).or_default().push(value);
+    }
+    groups
+}
+
+/// eg. "email: a@example.com, b@example.com; user: admin"
+pub fn format_values(values: &[(String, String)]) -> String {
+    group(values).iter()
+        .map(|(name, values)| format!("{name}: {}", values.join(", ")))
+        .collect::<Vec<String>>()
+        .join("; ")
+}
@@ -0,0 +1,115 @@
+use std::borrow::Cow;
+
+use encoding_rs::{Encoding, UTF_8};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// titles longer than this are cut, some pages put a whole paragraph there
+pub const MAX_TITLE_CHARS: usize = 200;
+// where a <meta charset> is looked for
+const META_SNIFF_BYTES: usize = 1024;
+
+static META_CHARSET_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?([\w:.-]+)"#).unwrap());
+static TITLE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title").unwrap());
+
+/// Charset of the body, from the Content-Type header, then a <meta> tag, utf-8 otherwise.
+/// A byte order mark wins over both when decoding.
+pub fn charset(body: &[u8], content_type: Option<&str>) -> &'static Encoding {
+    let from_header = content_type
+        .and_then(|ct| ct.split(';').skip(1).find_map(|param| {
+            let (name, value) = param.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("charset").then(|| value.trim().trim_matches('"'))
+        }))
+        .and_then(|label| Encoding::for_label(label.as_bytes()));
+    if let Some(encoding) = from_header {
+        return encoding;
+    }
+    let head = String::from_utf8_lossy(&body[..body.len().min(META_SNIFF_BYTES)]);
+    META_CHARSET_REGEX.captures(&head)
+        .and_then(|caps| Encoding::for_label(caps[1].as_bytes()))
+        .unwrap_or(UTF_8)
+}
+
+pub fn decode<'a>(body: &'a [u8], content_type: Option<&str>) -> Cow<'a, str> {
+    let (text, _, _) = charset(body, content_type).decode(body);
+    text
+}
+
+// the entities seen in titles, anything else is kept as is
+fn named_entity(name: &str) -> Option<char> {
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "copy" => '©',
+        "reg" => '®',
+        "trade" => '™',
+        "hellip" => '…',
+        "mdash" => '—',
+        "ndash" => '–',
+        "laquo" => '«',
+        "raquo" => '»',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "middot" => '·',
+        "bull" => '•',
+        _ => return None,
+    })
+}
+
+/// Decode `&amp;`, `&#39;` and `&#x27;` style entities
+pub fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        // entity names are short, a lone '&' is left alone
+        let end = match rest.bytes().take(12).position(|b| b == b';') {
+            Some(end) => end,
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let name = &rest[1..end];
+        let c = match name.strip_prefix('#') {
+            Some(n) => match n.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => n.parse::<u32>().ok(),
+            }.and_then(char::from_u32),
+            None => named_entity(name),
+        };
+        match c {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Text of the <title> tag, entities decoded and white space collapsed
+pub fn title(body: &[u8], content_type: Option<&str>) -> Option<String> {
+    let text = decode(body, content_type);
+    let caps = TITLE_REGEX.captures(&text)?;
+    let title = decode_entities(&caps[1]);
+    let title: String = title.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if title.is_empty() {
+        return None;
+    }
+    Some(title.chars().take(MAX_TITLE_CHARS).collect())
+}
@@ -0,0 +1,30 @@
+//! kenshi, a fast web fuzzer.
+//!
+//! The command line tool is built on top of this crate, a scan can be
+//! embedded the same way: build [`Params`], hand them to a [`Scanner`]
+//! and consume the [`ScanEvent`] stream.
+
+pub mod calibration;
+pub mod checkpoint;
+pub mod cluster;
+pub mod common;
+pub mod concurrency;
+pub mod data_handler;
+pub mod data_type;
+pub mod error;
+pub mod expr;
+pub mod extract;
+pub mod html;
+pub mod lru;
+pub mod ratelimit;
+pub mod rawhttp;
+pub mod retry;
+pub mod scanner;
+pub mod sink;
+pub mod throttle;
+pub mod wordlist;
+mod dns_preheat;
+
+pub use data_type::{Params, ParamsBuilder, ScanEvent, ScanHit, ScanStats, ScanSummary, Stats};
+pub use scanner::Scanner;
+pub use sink::{OutputFormat, ResultSink};
@@ -0,0 +1,62 @@
+use std::collections::{BTreeMap, HashMap};
+
+use url::Url;
+
+/// Hosts kept by each per-host cache: calibration baselines, rate limiters and
+/// throttle backoffs. An evicted host starts over with a fresh entry.
+pub const HOST_CACHE_SIZE: usize = 10_000;
+
+/// Key of the per-host caches, the origin of the url (scheme, host and port)
+pub fn host_of(url: &str) -> Option<String> {
+    Url::parse(url).ok().map(|url| url.origin().ascii_serialization())
+}
+
+/// Least recently used eviction, the scan keeps running with millions of hosts.
+pub struct LruCache<V: Clone> {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (u64, V)>,
+    // last use -> key, the first one is evicted
+    order: BTreeMap<u64, String>,
+}
+
+impl<V: Clone> LruCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<V> {
+        self.tick += 1;
+        let (last_use, value) = self.entries.get_mut(key)?;
+        self.order.remove(last_use);
+        self.order.insert(self.tick, key.to_string());
+        *last_use = self.tick;
+        Some(value.clone())
+    }
+
+    pub fn insert(&mut self, key: &str, value: V) {
+        self.tick += 1;
+        if let Some((last_use, _)) = self.entries.remove(key) {
+            self.order.remove(&last_use);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.order.insert(self.tick, key.to_string());
+        self.entries.insert(key.to_string(), (self.tick, value));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -1,90 +1,41 @@
 use std::{env};
-use std::collections::{HashSet, LinkedList};
 use std::fs;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::io::Write;
 use std::process::exit;
-use std::sync::{Arc, RwLock};
-use std::sync::atomic::AtomicBool;
+use std::str::FromStr;
+use std::time::Duration;
 
-use chashmap::CHashMap;
 use console::Term;
+use futures::StreamExt;
 use getopts::Options;
 use is_terminal::IsTerminal;
-use lazy_static::lazy_static;
 use log::{LevelFilter};
 use rand::{Rng};
+use regex::Regex;
 use sysinfo::{System, SystemExt};
 
-use crate::common::*;
+use kenshi::checkpoint::{Checkpoint, CHECKPOINT_DIR};
+use kenshi::cluster::MAX_CLUSTERS;
+use kenshi::common::*;
 #[cfg(unix)]
-use crate::common::adjust_ulimit_size;
-use crate::data_type::*;
+use kenshi::common::adjust_ulimit_size;
+use kenshi::data_type::*;
+use kenshi::error::ErrorClass;
+use kenshi::ratelimit::parse_rate;
+use kenshi::rawhttp::{DEFAULT_REQUEST_PROTO, parse_header, RequestTemplate};
+use kenshi::retry::{parse_classes, RetryPolicy};
+use kenshi::scanner::Scanner;
+use kenshi::sink::{Column, MAX_EXTRACT_SEEN, split_format};
+use kenshi::throttle::{MAX_PAYLOAD_THROTTLED, ThrottleAction};
+use kenshi::wordlist::{DEDUP_FALSE_POSITIVE, DEDUP_STDIN_LINES, DEFAULT_KEYWORD, FuzzMode, split_keyword, Wordlist, WordlistSet};
+
 use crate::params_parse::{filter_params, match_params, opt_int_parm};
-use crate::scanner::Scanner;
 
-mod common;
-mod scanner;
-mod data_type;
-mod data_handler;
-mod error;
 mod tests;
 mod params_parse;
-mod rawhttp;
-mod dns_preheat;
-
-#[derive(Debug)]
-struct Queue<T> {
-    queue: LinkedList<T>,
-}
-
-impl<T> Queue<T> {
-    fn new() -> Self {
-        Queue {
-            queue: LinkedList::new(),
-        }
-    }
-
-    fn enqueue(&mut self, item: T) {
-        self.queue.push_back(item);
-    }
-    fn dequeue(&mut self) -> Option<T> {
-        self.queue.pop_front()
-    }
-}
-
-lazy_static! {
-    static ref G_RESPONSE: Arc<RwLock<Queue<HttpResp>>> = {
-        let queue = Queue::new();
-        let rwlock = RwLock::new(queue);
-        Arc::new(rwlock)
-    };
-    pub static ref G_LOOP_BREAK: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
-    pub static ref G_SCAN_MODE: Arc<ScanMode> = Arc::new(ScanMode::Debug);
-
-    pub static ref G_STATS: Arc<CHashMap<Stats, u32>> = {
-        let stats = CHashMap::new();
-        stats.insert(Stats::IOErr, 0);
-        stats.insert(Stats::Done, 0);
-        stats.insert(Stats::Hits, 0);
-        stats.insert(Stats::TimeOut, 0);
-        stats.insert(Stats::DNSErr, 0);
-        stats.insert(Stats::C404, 0);
-        stats.insert(Stats::C200, 0);
-        stats.insert(Stats::C502, 0);
-        stats.insert(Stats::C500, 0);
-        stats.insert(Stats::C403, 0);
-        stats.insert(Stats::C401, 0);
-        stats.insert(Stats::C302, 0);
-        stats.insert(Stats::C301, 0);
-        stats.insert(Stats::C000, 0);
-        Arc::new(stats)
-    };
-}
 
 const G_DEFAULT_FILE_DESC_LIMIT: u64 = 65535;
-const G_DEFAULT_CONCURRENT_NUM: u32 = 500;
 const G_DEFAULT_MATCHES_STATUS_CODE: &str = "200,301,403,401,500";
 const G_DEFAULT_LOGFILE: &str = "kenshi.log";
 pub const VERSION: &str = "v0.1.3";
@@ -94,58 +45,178 @@ fn print_usage(program: &str, opts: Options) {
     println!("{}", opts.usage(&brief));
 }
 
-fn print_start_info(option: &data_type::Options) {
-    println!("wordlist: {}/lines", option.params.wordlist_len);
-    println!("concurrent: {}", option.params.concurrent_num);
-    println!("retries: {}", option.params.request_retries);
-    println!("request timeout: {}/s", option.params.request_timeout);
-    println!("user-agent: {}", option.params.user_agent);
+fn print_start_info(sys: &System, params: &Params) {
+    if params.wordlist_len > 0 {
+        println!("wordlist: {}/lines", params.wordlist_len);
+        if params.wordlist.lists.len() > 1 {
+            println!("wordlist mode: {}", params.wordlist.mode);
+        }
+    } else {
+        println!("wordlist: stdin");
+    }
+    if params.request.method != "GET" || !params.request.headers.is_empty()
+        || params.request.body.is_some() {
+        println!("request: {} {}", params.request.method, params.request.url);
+    }
+    if let Some(checkpoint) = params.checkpoint.as_ref() {
+        println!("scan id: {}", checkpoint.id);
+        if params.resumed {
+            println!("resume from: {}/{}", checkpoint.progress.count(), checkpoint.total);
+        }
+    }
+    match params.auto_concurrency {
+        Some((min, max)) => println!("concurrent: {} (auto, {min}-{max})", params.concurrent_num),
+        None => println!("concurrent: {}", params.concurrent_num),
+    }
+    println!("workers: {}", params.worker_num);
+    println!("retries: {}", params.request_retries);
+    if params.request_retries > 1 {
+        let classes: Vec<String> = params.retry_policy.classes.iter().map(|c| c.to_string()).collect();
+        println!("retry on: {}, delay: {}ms", classes.join(","), params.retry_policy.base_delay.as_millis());
+    }
+    if let Some(rate) = params.rate {
+        println!("rate: {rate:.1}/s");
+    }
+    if let Some(rate) = params.host_rate {
+        println!("host rate: {rate:.1}/s");
+    }
+    match params.backoff {
+        Some((0, _)) => println!("backoff: 429/503, no limit"),
+        Some((max, action)) => println!("backoff: 429/503, {action} after {max} pauses"),
+        None => println!("backoff: off"),
+    }
+    println!("request timeout: {}/s", params.request_timeout);
+    println!("user-agent: {}", params.user_agent);
     println!("dns servers: {}", DEFAULT_DNS_SERVERS.len());
-    println!("dns try: {}", option.params.dns_try);
-    println!("memory: {:.2}/Gb", bytes_to_gb(option.sys.total_memory()));
-    println!("swap: {:.2}/Mb", bytes_to_mb(option.sys.total_swap()));
-    println!("mode: {}", option.params.scan_mode.to_string());
-    if option.params.scan_mode == ScanMode::Debug {
+    println!("dns try: {}", params.dns_try);
+    if let Some(expr) = &params.custom_matches {
+        println!("match: {expr}");
+    }
+    if let Some(expr) = params.custom_filters.as_ref().and_then(|f| f.expr.as_ref()) {
+        println!("filter: {expr}");
+    }
+    println!("auto calibration: {}", params.auto_calibration);
+    println!("host calibration: {}", params.host_calibration);
+    for regex in params.extract.iter() {
+        println!("extract: {}", regex.as_str());
+    }
+    if params.cluster_size > 0 {
+        println!("cluster: {} hits", params.cluster_size);
+    }
+    println!("memory: {:.2}/Gb", bytes_to_gb(sys.total_memory()));
+    println!("swap: {:.2}/Mb", bytes_to_mb(sys.total_swap()));
+    println!("mode: {}", params.scan_mode);
+    if params.scan_mode == ScanMode::Debug {
         println!("logfile: {G_DEFAULT_LOGFILE}");
     }
     println!();
 }
 
+fn print_summary(summary: &ScanSummary) {
+    let stat = |s: Stats| summary.get(&s);
+    println!();
+    println!("done: {}, hits: {}, elapsed: {:.2}s", stat(Stats::Done), stat(Stats::Hits), summary.elapsed.as_secs_f64());
+    println!("200: {}, 404: {}, 301: {}, 302: {}, 403: {}, 401: {}, 500: {}, 502: {}, other: {}",
+             stat(Stats::C200), stat(Stats::C404), stat(Stats::C301), stat(Stats::C302),
+             stat(Stats::C403), stat(Stats::C401), stat(Stats::C500), stat(Stats::C502), stat(Stats::C000));
+    println!("timeout: {}, io error: {}, dns error: {}",
+             stat(Stats::TimeOut), stat(Stats::IOErr), stat(Stats::DNSErr));
+    println!("refused: {}, reset: {}, tls error: {}, proxy error: {}, redirect error: {}, decode error: {}",
+             stat(Stats::ConnRefused), stat(Stats::ConnReset), stat(Stats::TlsErr),
+             stat(Stats::ProxyErr), stat(Stats::RedirectErr), stat(Stats::DecodeErr));
+    if stat(Stats::Probes) > 0 {
+        println!("calibration probes: {}", stat(Stats::Probes));
+    }
+    if stat(Stats::Throttled) > 0 {
+        println!("throttled: {}, dropped: {}", stat(Stats::Throttled), stat(Stats::Dropped));
+    }
+    let retries: Vec<String> = ErrorClass::all().iter()
+        .filter(|c| stat(c.retry_stat()) > 0)
+        .map(|c| format!("{c}: {}", stat(c.retry_stat())))
+        .collect();
+    if !retries.is_empty() {
+        println!("retries: {}", retries.join(", "));
+    }
+    for host in summary.dropped_hosts.iter() {
+        println!("dropped host: {host}");
+    }
+    for (representative, members) in summary.clusters.iter() {
+        println!("cluster: {members} similar responses to {representative}");
+    }
+}
+
 pub fn parse_args(args: &[String]) -> Result<Params, String> {
+    parse_args_resume(args, None)
+}
+
+fn parse_args_resume(args: &[String], resume_from: Option<Checkpoint>) -> Result<Params, String> {
     let program = args[0].clone();
     let mut opts = Options::new();
     // basic
-    opts.optopt("u", "url", "required. Test url", "<url>");
-    opts.optopt("w", "wordlist", "required. Wordlist file path. eg. '/path/to/wordlist'", "<file>");
-    opts.optopt("o", "output", "Output result", "<file>");
+    opts.optopt("u", "url", "required, unless --request is used. Test url", "<url>");
+    opts.optmulti("w", "wordlist", "required. Wordlist file path and (optional) keyword separated by colon. eg. '/path/to/wordlist:KEYWORD'", "<file[:KEYWORD]>");
+    opts.optmulti("o", "output", "Output file and (optional) format separated by colon, inferred from the extension otherwise. Formats: text, jsonl, csv. Multiple -o flags are accepted.", "<file[:format]>");
+    opts.optflag("", "title", "Show the page title of each hit");
+    opts.optflag("", "server", "Show the Server header of each hit");
+    opts.optflag("", "location", "Show the redirect target of each hit");
+    opts.optmulti("", "extract", "Collect regexp captures from each hit, named groups keep their name. Multiple --extract flags are accepted.", "<regexp>");
+    opts.optopt("", "extract-out", &format!("Write every extracted value once to a file, one per line. Past {MAX_EXTRACT_SEEN} distinct values new ones may repeat"), "<file>");
 
     // match option
+    opts.optopt("", "match", r#"Match expression, eg. 'status in [200,403] && size > 1000 && !body ~ "Not Found"'. Fields: status, size, lines, words, ttfb, time, body, url, headers, content_type"#, "<expr>");
     opts.optflag("", "or-match", r#"Any one of these hits will do. (default: and)"#);
-    opts.optopt("", "mc", "Match HTTP status codes, or \"all\" for everything.", &format!("<{G_DEFAULT_MATCHES_STATUS_CODE}>"));
+    opts.optopt("", "mc", "Match HTTP status codes. Comma separated list of codes, classes and ranges, \"all\" for everything and \"!\" to exclude. eg. 2xx,3xx,!304", &format!("<{G_DEFAULT_MATCHES_STATUS_CODE}>"));
     opts.optopt("", "mr", "Match regexp", "<regexp>");
-    opts.optopt("", "ms", r#"Match HTTP response size"#, "<int>");
-    opts.optopt("", "ml", r#"Match amount of lines in response"#, "<int>");
-    //opts.optopt("", "mt", "Match how many milliseconds to the first response byte, either greater or less than. EG: >100 or <100", "");
+    opts.optopt("", "ms", r#"Match HTTP response size. Comma separated list of numeric rules. eg. --ms "<100,>1000,10-50,1234""#, "<rules...>");
+    opts.optopt("", "ml", "Match amount of lines in response. Comma separated list of numeric rules", "<rules...>");
+    opts.optopt("", "mw", "Match amount of words in response. Comma separated list of numeric rules", "<rules...>");
+    opts.optopt("", "mh", "Match regexp over the response headers, one \"Name: value\" per line, case-insensitive", "<regexp>");
+    opts.optopt("", "mct", "Match content type. Comma separated list, eg. text/html,application/json", "<type,...>");
+    opts.optopt("", "mt", r#"Match milliseconds to the first response byte. Comma separated list of numeric rules. eg. --mt ">5000""#, "<rules...>");
 
     // filter option
+    opts.optopt("", "filter", "Filter expression, matching responses are dropped. Same syntax as --match", "<expr>");
     opts.optflag("", "or-filter", r#"Any one of these hits will do. (default: and)"#);
-    opts.optopt("", "fc", "Filter HTTP status codes from response. Comma separated list of codes and ranges", "<int,...>");
-    opts.optopt("", "fl", "Filter by amount of lines in response. Comma separated list of line counts and ranges. eg. --fl 123,1234 ", "<int,...>");
+    opts.optopt("", "fc", "Filter HTTP status codes from response. Comma separated list of codes, classes and ranges, \"!\" to exclude. eg. 4xx,!403", "<int,...>");
+    opts.optopt("", "fl", "Filter by amount of lines in response. Comma separated list of numeric rules. eg. --fl 123,1234", "<rules...>");
     opts.optopt("", "fr", r#"Filter regexp"#, "<regexp>");
-    opts.optopt("", "fs", r#"Filter HTTP response size. Comma separated list of sizes and ranges. eg. --fs "<100,>1000,10-50,1234""#, "<rules...>");
+    opts.optopt("", "fw", "Filter by amount of words in response. Comma separated list of numeric rules", "<rules...>");
+    opts.optopt("", "fh", "Filter regexp over the response headers, one \"Name: value\" per line, case-insensitive", "<regexp>");
+    opts.optopt("", "fct", "Filter content type. Comma separated list, eg. image/png,text/css", "<type,...>");
+    opts.optopt("", "ft", r#"Filter milliseconds to the first response byte. Comma separated list of numeric rules. eg. --ft "<100""#, "<rules...>");
+    opts.optopt("", "fs", r#"Filter HTTP response size. Comma separated list of numeric rules: 1234, >1000, <=10, 10-50 (inclusive) or 10..50 (end excluded)"#, "<rules...>");
+    opts.optflag("", "ac", "Automatically calibrate filtering options, random paths are probed before the scan and their responses filtered");
+    opts.optflag("", "ach", "Per host auto-calibration, a random path baseline is fetched for each host and matching responses are filtered. Costs 3 extra requests per host, the first response of a host waits for them");
+    opts.optopt("", "cluster", &format!("Group similar responses (simhash) and hide a group once it has more than <int> hits. Up to {MAX_CLUSTERS} groups, later pages unlike all of them are shown. 0 disables it. default: 0"), "<int>");
 
     // scan
     opts.optopt("", "rt", "Request timeout seconds", "<int>");
     opts.optopt("c", "concurrent", &format!("Number of concurrent requests. default: {G_DEFAULT_CONCURRENT_NUM}"), "<int>");
     opts.optopt("", "follow-redirect", "enable redirect 301/302. disabled by default", "<int>");
     opts.optopt("r", "retries", "Number of failed retry requests", "<int>");
+    opts.optopt("", "retry-on", "Error classes retried with -r. Comma separated list of timeout, refused, reset, dns, tls, body, other, or all/none. default: timeout,refused,reset,body,other", "<class,...>");
+    opts.optopt("", "retry-delay", &format!("Milliseconds before the first retry, doubled on each one, with jitter. default: {G_DEFAULT_RETRY_DELAY_MS}"), "<ms>");
+    opts.optflag("", "auto-concurrency", "Adjust the concurrency to the target, lowered on timeouts and io errors and raised while they stay rare. -c is the starting point. Decided after every window of max(concurrency, 50) finished requests, the counts then start over");
+    opts.optopt("", "min-concurrency", &format!("Lowest concurrency with --auto-concurrency. default: {G_DEFAULT_MIN_CONCURRENCY}"), "<int>");
+    opts.optopt("", "max-concurrency", &format!("Highest concurrency with --auto-concurrency. default: {G_DEFAULT_MAX_CONCURRENCY}"), "<int>");
+    opts.optopt("", "rate", "Maximum requests per second over the whole scan. eg. 50/s, 600/m", "<N/s>");
+    opts.optopt("", "host-rate", "Maximum requests per second to each host. eg. 5/s", "<N/s>");
+    opts.optopt("", "max-throttle", &format!("Pauses in a row a host answering 429, or 503 with Retry-After, gets before --throttle-action, 0 for no limit. A payload is sent again {MAX_PAYLOAD_THROTTLED} times at most. default: {G_DEFAULT_MAX_THROTTLE}"), "<int>");
+    opts.optopt("", "throttle-action", "What happens to a host still throttling after --max-throttle pauses: drop skips its payloads, abort stops the scan. default: drop", "<drop|abort>");
+    opts.optflag("", "no-backoff", "Don't pause on 429, or 503 with Retry-After, treat them as any other response");
+    opts.optopt("", "workers", &format!("Number of threads matching responses and running scripts. default: {G_DEFAULT_WORKER_NUM}"), "<int>");
+    opts.optopt("", "queue-size", &format!("Responses waiting to be matched before requests pause. default: {G_DEFAULT_QUEUE_SIZE}"), "<int>");
     opts.optflag("", "dns-try", "Try multiple sets of nameservers to mitigate dns resolution failures");
 
     // http option
+    opts.optopt("", "request", "File containing the raw http request, FUZZ can be placed in the method, path, headers or body. With -u, or a full url in the request line, the Host header is sent as written", "<file>");
+    opts.optopt("X", "method", "HTTP method to use (default: GET, POST with -d)", "<method>");
+    opts.optmulti("H", "header", r#"Header "Name: value", separated by colon. Multiple -H flags are accepted."#, "<header>");
+    opts.optopt("d", "data", "Request body, sent with POST unless -X is given", "<data>");
+    opts.optopt("", "request-proto", &format!("Protocol to use along with raw request (default: {DEFAULT_REQUEST_PROTO})"), "<proto>");
     /*
     opts.optopt("", "http1", "", "");
     opts.optopt("", "cookie", "", "1");
-    opts.optopt("", "raw", "", "1");
     opts.optflag("", "test-raw", r#"debug raw request"#);
     opts.optopt("r", "valid-cert", "Only valid certificate targets will be tested", "1");
     opts.optopt("", "no-color", "I like black and white.", "socks5://1.1.1.1:1080");
@@ -159,18 +230,14 @@ pub fn parse_args(args: &[String]) -> Result<Params, String> {
     opts.optopt("e", "ext", "Comma separated list of extensions. Extends FUZZ keyword.", "");
     opts.optopt("s", "script", "lua script(This is an experimental feature)", "");
     // mode
-    //opts.optopt("m", "mode", "Multi-wordlist operation mode. Available modes: clusterbomb, pitchfork, sniper (default: clusterbomb)", "");
+    opts.optflag("", "dedup", &format!("Skip repeated wordlist lines, repeated pairs in pitchfork mode. Lines seen go in a bloom filter of about 2.4 bytes per line, 256MB at most, stdin is taken as {DEDUP_STDIN_LINES} lines. A new line is skipped by mistake with odds {DEDUP_FALSE_POSITIVE}"));
+    opts.optopt("m", "mode", "Multi-wordlist operation mode. Available modes: clusterbomb, pitchfork, sniper (default: clusterbomb)", "<mode>");
+    opts.optopt("", "resume", &format!("Resume an interrupted scan from its checkpoint in {CHECKPOINT_DIR}/"), "<id>");
     opts.optflag("", "silent", "silent mode");
     opts.optflag("v", "stats", "Display detailed scanning status");
     opts.optflag("", "vv", "show version");
     //opts.optopt("", "dns-list", "Specify a list of name servers", "Url or File");
     //opts.optopt("p", "port", "binding port", "PORT");
-    /*
-        -mode
-        -request            File containing the raw http request
-        -request-proto      Protocol to use along with raw request (default: https)
-        -w                  Wordlist file path and (optional) keyword separated by colon. eg. '/path/to/wordlist:KEYWORD'
-     */
     opts.optflag("h", "help", "print this help menu");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
@@ -190,29 +257,73 @@ pub fn parse_args(args: &[String]) -> Result<Params, String> {
         exit(0);
     }
 
-    // output file
-    let result_path = match matches.opt_str("o") {
-        Some(result_path) => {
-            if file_exists(&result_path) {
-                return Err(format!("{} exists, please note.", &result_path));
-            }
-            Some(result_path)
+    // the saved command line is parsed again
+    if let Some(id) = matches.opt_str("resume") {
+        if resume_from.is_some() {
+            return Err("[resume] nested --resume.".to_string());
         }
-        None => None
-    };
+        let checkpoint = Checkpoint::load(&id)?;
+        let saved_args = checkpoint.args.clone();
+        return parse_args_resume(&saved_args, Some(checkpoint));
+    }
 
+    // output files, the format follows the extension unless given after a colon
+    let mut outputs = vec![];
+    for value in matches.opt_strs("o") {
+        let (path, format) = split_format(&value);
+        // a resumed scan keeps appending to it
+        if resume_from.is_none() && file_exists(&path) {
+            return Err(format!("{} exists, please note.", &path));
+        }
+        outputs.push((path, format));
+    }
+    let columns = [("title", Column::Title), ("server", Column::Server), ("location", Column::Location)]
+        .into_iter()
+        .filter(|(name, _)| matches.opt_present(name))
+        .map(|(_, column)| column)
+        .collect();
+    let mut extract = vec![];
+    for r in matches.opt_strs("extract") {
+        extract.push(Regex::new(&r).map_err(|e| format!("[extract] {e}"))?);
+    }
+    let extract_output = matches.opt_str("extract-out");
+    if let (true, Some(path)) = (resume_from.is_none(), &extract_output) {
+        if file_exists(path) {
+            return Err(format!("{} exists, please note.", path));
+        }
+    }
 
-    let fuzz_url = match matches.opt_str("u") {
-        Some(f) => {
-            if !f.contains("FUZZ") {
-                return Err(r#"not found "FUZZ" str."#.to_string());
+    let mut request = match matches.opt_str("request") {
+        Some(path) => {
+            let proto = matches.opt_str("request-proto").unwrap_or_else(|| DEFAULT_REQUEST_PROTO.to_string());
+            if proto != "http" && proto != "https" {
+                return Err(format!("[request] unsupported protocol \"{proto}\"."));
             }
-            f
+            // -u overrides the url of the raw request
+            RequestTemplate::from_file(&path, &proto, matches.opt_str("u").as_deref())?
         }
-        None => {
-            return Err("fuzz url is empty.".to_string());
+        None => match matches.opt_str("u") {
+            Some(f) => RequestTemplate::from_url(&f),
+            None => {
+                return Err("fuzz url is empty.".to_string());
+            }
         }
     };
+    if let Some(method) = matches.opt_str("X") {
+        request.method = method;
+    }
+    for header in matches.opt_strs("H") {
+        let (name, value) = parse_header(&header)?;
+        request.set_header(&name, &value);
+    }
+    if let Some(data) = matches.opt_str("d") {
+        // like curl, data without -X is a POST
+        if !matches.opt_present("X") && request.method == "GET" {
+            request.method = "POST".to_string();
+        }
+        request.body = Some(data.into_bytes());
+    }
+    request.validate()?;
 
 
     // proxy support
@@ -231,15 +342,45 @@ pub fn parse_args(args: &[String]) -> Result<Params, String> {
     }
 
     let request_retries = opt_int_parm("r", &matches, 1);
-    let mut concurrent_num = opt_int_parm("c", &matches, G_DEFAULT_CONCURRENT_NUM as usize);
-    let ulimit = concurrent_num as u64 * 2;
+    let mut retry_policy = RetryPolicy::default();
+    if let Some(classes) = matches.opt_str("retry-on") {
+        retry_policy.classes = parse_classes(&classes)?;
+    }
+    retry_policy.base_delay = Duration::from_millis(opt_int_parm("retry-delay", &matches, G_DEFAULT_RETRY_DELAY_MS as usize) as u64);
+    let rate = matches.opt_str("rate").map(|r| parse_rate(&r)).transpose()?;
+    let host_rate = matches.opt_str("host-rate").map(|r| parse_rate(&r)).transpose()?;
+    let backoff = match matches.opt_present("no-backoff") {
+        true => None,
+        false => {
+            let action = match matches.opt_str("throttle-action") {
+                Some(a) => ThrottleAction::from_str(&a)?,
+                None => ThrottleAction::Drop,
+            };
+            Some((opt_int_parm("max-throttle", &matches, G_DEFAULT_MAX_THROTTLE), action))
+        }
+    };
+    let mut concurrent_num = opt_int_parm("c", &matches, G_DEFAULT_CONCURRENT_NUM);
+    let auto_concurrency = match matches.opt_present("auto-concurrency") {
+        true => {
+            let min = opt_int_parm("min-concurrency", &matches, G_DEFAULT_MIN_CONCURRENCY).max(1);
+            let max = opt_int_parm("max-concurrency", &matches, G_DEFAULT_MAX_CONCURRENCY);
+            if max < min {
+                return Err(format!("[params] --max-concurrency {max} is below --min-concurrency {min}"));
+            }
+            Some((min, max))
+        }
+        false => None,
+    };
+    let ulimit = auto_concurrency.map_or(concurrent_num, |(_, max)| max) as u64 * 2;
 
     #[cfg(unix)]
     if ulimit > G_DEFAULT_FILE_DESC_LIMIT {
         let _ = adjust_ulimit_size(G_DEFAULT_FILE_DESC_LIMIT) as usize;
     }
 
-    let request_timeout_sec = opt_int_parm("rt", &matches, 10) as u64;
+    let request_timeout_sec = opt_int_parm("rt", &matches, G_DEFAULT_REQUEST_TIMEOUT as usize) as u64;
+    let worker_num = opt_int_parm("workers", &matches, G_DEFAULT_WORKER_NUM).max(1);
+    let queue_size = opt_int_parm("queue-size", &matches, G_DEFAULT_QUEUE_SIZE).max(1);
     let follow_redirect_num = opt_int_parm("follow-redirect", &matches, 0);
 
     let custom_filter = match filter_params(&matches) {
@@ -251,62 +392,79 @@ pub fn parse_args(args: &[String]) -> Result<Params, String> {
         Err(e) => return Err(e)
     };
 
-    // wordlist load
-    let term = Term::stdout();
-    print!("Load... ");
-    let _ = std::io::stdout().flush();
-    let mut wordlist: HashSet<String> = HashSet::new();
-    let is_tty = std::io::stdin().is_terminal();
-    if is_tty {
-        if let Some(path) = matches.opt_str("w") {
-            if !file_exists(&path) {
-                let _ = term.clear_line();
-                return Err(format!(r#"wordlist "{path}" non-existent."#));
-            }
-            //Stream processing seems more appropriate
-            let file = File::open(path).expect("Failed to open file");
-            let reader = BufReader::new(&file);
-            for payload in reader.lines() {
-                wordlist.insert(payload.unwrap());
-            }
-        }else {
-            let _ = term.clear_line();
-            return Err("missing -w param, -h Get Help.".to_owned())
-        }
-    } else {
-        for line in std::io::stdin().lines().flatten() {
-            wordlist.insert(line);
-        }
-    }
-
     // dirsearch ext replace
+    let mut extensions = None;
     if matches.opt_present("D") {
         let mut ext_s: Vec<String> = vec![];
         if let Some(s) = matches.opt_str("e") {
             ext_s = s.split(',').map(|s| s.to_string()).collect();
         }
         if ext_s.is_empty() {
-            let _ = term.clear_line();
             return Err("extensions invalid. for example:-e php,db,conf,bak".to_string());
         }
+        extensions = Some(ext_s);
+    }
 
-        let mut new_wordlist: HashSet<String> = HashSet::new();
-        for line in wordlist.iter() {
-            if line.contains("%EXT%") {
-                for s in ext_s.iter() {
-                    new_wordlist.insert(line.replace("%EXT%", s));
+    // wordlist load, payloads are read lazily while scanning
+    let term = Term::stdout();
+    print!("Load... ");
+    let _ = std::io::stdout().flush();
+    let is_tty = std::io::stdin().is_terminal();
+    let wordlist_opts = matches.opt_strs("w");
+    let mut lists: Vec<Wordlist> = vec![];
+    if !wordlist_opts.is_empty() {
+        for opt in wordlist_opts.iter() {
+            let (path, keyword) = split_keyword(opt);
+            if lists.iter().any(|w| w.keyword == keyword) {
+                let _ = term.clear_line();
+                return Err(format!(r#"keyword "{keyword}" is used by more than one wordlist."#));
+            }
+            if !file_exists(&path) {
+                let _ = term.clear_line();
+                return Err(format!(r#"wordlist "{path}" non-existent."#));
+            }
+            // %EXT% only extends the FUZZ keyword
+            let ext_s = if keyword == DEFAULT_KEYWORD { extensions.clone() } else { None };
+            match Wordlist::from_file(&path, &keyword, ext_s) {
+                Ok(w) => lists.push(w),
+                Err(e) => {
+                    let _ = term.clear_line();
+                    return Err(format!(r#"wordlist "{path}" read failed. {e}"#));
                 }
-                continue;
             }
-            new_wordlist.insert(line.to_string());
         }
-        wordlist = new_wordlist;
+    } else if !is_tty {
+        lists.push(Wordlist::from_stdin(extensions));
+    } else {
+        let _ = term.clear_line();
+        return Err("missing -w param, -h Get Help.".to_owned())
+    }
+    let mode = match matches.opt_str("m") {
+        Some(m) => match FuzzMode::from_str(&m) {
+            Ok(m) => m,
+            Err(e) => {
+                let _ = term.clear_line();
+                return Err(e);
+            }
+        },
+        None => FuzzMode::Clusterbomb,
+    };
+    let mut wordlist = WordlistSet::new(lists, mode);
+    if matches.opt_present("dedup") {
+        wordlist = wordlist.with_dedup();
     }
     println!("DONE");
 
+    for keyword in wordlist.keywords() {
+        if !request.contains(&keyword) {
+            return Err(format!(r#"not found "{keyword}" str."#));
+        }
+    }
+
     // Dealing with some strange situations
-    if concurrent_num > wordlist.len() {
-        concurrent_num = wordlist.len() / 2;
+    let wordlist_len = wordlist.len();
+    if wordlist_len > 0 && concurrent_num > wordlist_len {
+        concurrent_num = wordlist_len / 2;
         if concurrent_num == 0 {
             concurrent_num = 1
         }
@@ -356,17 +514,35 @@ pub fn parse_args(args: &[String]) -> Result<Params, String> {
     let mut rng = rand::thread_rng();
     let ua_str = COMMON_USER_AGENTS[rng.gen_range(0..COMMON_USER_AGENTS.len() - 1)];
 
+    let resumed = resume_from.is_some();
+    let mut checkpoint = match resume_from {
+        Some(c) => c,
+        None => Checkpoint::new(&Checkpoint::new_id(), args),
+    };
+    checkpoint.use_wordlist(&wordlist, resumed)?;
+
     Ok(Params {
         user_agent: ua_str.to_owned(),
         request_timeout: request_timeout_sec,
-        result_file: result_path,
-        wordlist_len: wordlist.len(),
+        outputs,
+        columns,
+        wordlist_len,
         wordlist,
-        fuzz_url,
+        request,
+        checkpoint: Some(checkpoint),
+        resumed,
+        terminal: true,
         print_state,
         concurrent_num,
+        auto_concurrency,
+        worker_num,
+        queue_size,
         custom_matches,
         request_retries,
+        retry_policy,
+        rate,
+        host_rate,
+        backoff,
         proxy_server,
         dns_try,
         proxy_user: proxy_user.to_owned(),
@@ -375,6 +551,11 @@ pub fn parse_args(args: &[String]) -> Result<Params, String> {
         scan_mode,
         follow_redirect: follow_redirect_num,
         custom_filters: custom_filter,
+        auto_calibration: matches.opt_present("ac"),
+        host_calibration: matches.opt_present("ach"),
+        cluster_size: opt_int_parm("cluster", &matches, 0),
+        extract,
+        extract_output,
         no_color,
     })
 }
@@ -397,7 +578,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .init();
 
-    if fs::create_dir_all("data").is_err() {
+    if fs::create_dir_all(CHECKPOINT_DIR).is_err() {
         log::error!("cache directory create failed.");
         exit(1);
     }
@@ -412,10 +593,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         exit(1)
     }
     let params = params.unwrap();
-    let options = data_type::Options {
-        sys: &sys,
-        params: params.clone(),
-    };
     // off log
     if params.scan_mode != ScanMode::Debug {
         log::set_max_level(log::LevelFilter::Off);
@@ -424,10 +601,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
     params.print_state.then(|| {
-        print_start_info(&options)
+        print_start_info(&sys, &params)
     });
 
-    let scan = Scanner::new(&options);
-    scan.start().await;
+    let print_state = params.print_state;
+    let scan_id = params.checkpoint.as_ref().map(|c| c.id.clone());
+    let mut events = Scanner::new(params).run();
+    while let Some(event) = events.next().await {
+        match event {
+            // hits are already printed by the scanner
+            ScanEvent::Hit(_) => {}
+            ScanEvent::Error(e) => {
+                eprintln!("{e}");
+                exit(1);
+            }
+            ScanEvent::Finished(summary) => {
+                if print_state {
+                    print_summary(&summary);
+                }
+                if let (true, Some(id)) = (summary.interrupted, &scan_id) {
+                    eprintln!("interrupted, continue with: --resume {id}");
+                }
+            }
+        }
+    }
     Ok(())
 }
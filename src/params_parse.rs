@@ -1,6 +1,6 @@
-use std::collections::HashSet;
 use regex::Regex;
-use crate::data_type::{FilterRules, Matches};
+use kenshi::data_type::FilterRules;
+use kenshi::expr::{Expr, NumRule, quote};
 use crate::G_DEFAULT_MATCHES_STATUS_CODE;
 
 pub fn opt_int_parm(name: &str, matches: &getopts::Matches, default: usize) -> usize {
@@ -12,69 +12,56 @@ pub fn opt_int_parm(name: &str, matches: &getopts::Matches, default: usize) -> u
     default
 }
 
-pub fn opt_int_some_parm(name: &str, matches: &getopts::Matches) -> Option<usize> {
-    if let Some(s) = matches.opt_str(name) {
-        if let Ok(u) = s.parse::<usize>() {
-            return Some(u);
-        }
+// a status code or "a-b", eg. "404" or "400-499"
+fn status_range(value: &str) -> Option<(u16, u16)> {
+    let code = |s: &str| s.trim().parse::<u16>().ok().filter(|c| (100..=999).contains(c));
+    // class, eg. "4xx"
+    if let Some(class) = value.to_lowercase().strip_suffix("xx") {
+        let class = class.parse::<u16>().ok().filter(|c| (1..=9).contains(c))?;
+        return Some((class * 100, class * 100 + 99));
     }
-    None
-}
-
-pub fn opt_usize_split(name: &str, defaults: &str, matches: &getopts::Matches) -> Option<HashSet<usize>> {
-    let mut codes: HashSet<usize> = HashSet::new();
-    match matches.opt_str(name) {
-        Some(s) => {
-            let split: Vec<&str> = s.split(',').collect();
-            for code in split {
-                if let Ok(u) = code.parse::<usize>() {
-                    codes.insert(u);
-                }
-            }
-            Some(codes)
-        }
-        None => {
-            if defaults.is_empty() {
-                return None
-            }
-            let split: Vec<&str> = defaults.split(',').collect();
-            for code in split {
-                if let Ok(u) = code.parse::<usize>() {
-                    codes.insert(u);
-                }
-            }
-            Some(codes)
+    match value.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (code(start)?, code(end)?);
+            (start <= end).then_some((start, end))
         }
+        None => code(value).map(|c| (c, c)),
     }
 }
 
-pub fn opt_int_split(name: &str, defaults: &str, matches: &getopts::Matches) -> Option<HashSet<u16>> {
-    let mut codes: HashSet<u16> = HashSet::new();
-    match matches.opt_str(name) {
-        Some(s) => {
-            let split: Vec<&str> = s.split(',').collect();
-            for code in split {
-                if let Ok(u) = code.parse::<u16>() {
-                    codes.insert(u);
-                }
-            }
-            Some(codes)
+/// Status code rules of --mc/--fc, eg. "2xx,3xx,!304", "all,!404" or "200,400-499".
+/// A "!" excludes codes from the others, only exclusions means any other code.
+/// None when every status code is accepted.
+pub fn status_rules(name: &str, value: &str) -> Result<Option<String>, String> {
+    let mut include = vec![];
+    let mut exclude = vec![];
+    let mut all = false;
+    for rule in value.split(',').map(|v| v.trim()) {
+        let (negated, rule) = match rule.strip_prefix('!') {
+            Some(r) => (true, r.trim()),
+            None => (false, rule),
+        };
+        if rule.eq_ignore_ascii_case("all") && !negated {
+            all = true;
+            continue;
         }
-        None => {
-            if defaults.is_empty() {
-                return None
-            }
-            let split: Vec<&str> = defaults.split(',').collect();
-            for code in split {
-                if let Ok(u) = code.parse::<u16>() {
-                    codes.insert(u);
-                }
-            }
-            codes.insert(200);
-            codes.insert(403);
-            Some(codes)
+        let (start, end) = status_range(rule)
+            .ok_or_else(|| format!("[params] --{name}: invalid status code \"{rule}\", expected eg. 200, 4xx, 500-599, all or !404"))?;
+        let range = if start == end { start.to_string() } else { format!("{start}-{end}") };
+        if negated {
+            exclude.push(range);
+        } else {
+            include.push(range);
         }
     }
+    let mut parts = vec![];
+    if !all && !include.is_empty() {
+        parts.push(format!("status in [{}]", include.join(",")));
+    }
+    if !exclude.is_empty() {
+        parts.push(format!("!(status in [{}])", exclude.join(",")));
+    }
+    Ok(join_parts(parts, " && "))
 }
 
 pub fn opt_vec_split(name: &str, matches: &getopts::Matches) -> Option<Vec<String>> {
@@ -88,65 +75,139 @@ pub fn opt_vec_split(name: &str, matches: &getopts::Matches) -> Option<Vec<Strin
     }
 }
 
-pub fn filter_params(matches: &getopts::Matches) -> Result<Option<FilterRules>, String> {
-    let filter_resp_regex = match matches.opt_str("fr") {
+// each part is wrapped, so they can be joined by && or ||
+fn join_parts(parts: Vec<String>, op: &str) -> Option<String> {
+    if parts.len() < 2 {
+        return parts.into_iter().next()
+    }
+    Some(parts.iter().map(|p| format!("({p})")).collect::<Vec<String>>().join(op))
+}
+
+fn regex_part(name: &str, field: &str, matches: &getopts::Matches) -> Result<Option<String>, String> {
+    match matches.opt_str(name) {
         Some(r) => {
-            match Regex::new(&r) {
-                Ok(r) => Some(r),
-                Err(e) => {
-                    return Err(e.to_string());
-                }
+            // header names come lowercased, "Server: nginx" has to match them
+            let r = if field == "headers" { format!("(?i){r}") } else { r };
+            if let Err(e) = Regex::new(&r) {
+                return Err(e.to_string());
             }
+            Ok(Some(format!("{field} ~ {}", quote(&r))))
         }
-        None => None
-    };
-    let filter_http_status_code = opt_int_split("fc","", matches);
-    let filter_resp_line = opt_usize_split("fl", "", matches);
+        None => Ok(None)
+    }
+}
 
-    let filter_resp_size = opt_vec_split("fs", matches);
-    // empty
-    if filter_resp_regex.is_none() && filter_http_status_code.is_none() &&
-        filter_resp_line.is_none() && filter_resp_size.is_none() {
-        return Ok(None)
-    }
-    Ok(Some(FilterRules{
-        regex: filter_resp_regex,
-        status_code: filter_http_status_code,
-        line_num: filter_resp_line,
-        resp_size: filter_resp_size,
-        and_and_and: matches.opt_present("or-filter").eq(&false),
-    }))
+// eg. "text/html,application/json", parameters like charset are ignored
+fn content_type_part(name: &str, matches: &getopts::Matches) -> Option<String> {
+    let types: Vec<String> = opt_vec_split(name, matches)?
+        .iter()
+        .filter(|t| !t.is_empty())
+        .map(|t| regex::escape(t))
+        .collect();
+    Some(format!("content_type ~ {}", quote(&format!("(?i)^({})\\s*(;|$)", types.join("|")))))
 }
 
-pub fn match_params(matches: &getopts::Matches) -> Result<Option<Matches>, String> {
-    // match
-    let match_regex = match matches.opt_str("mr") {
-        Some(r) => {
-            match Regex::new(&r) {
-                Ok(r) => Some(r),
-                Err(e) => {
-                    return Err(e.to_string());
-                }
-            }
-        }
-        None => None
+// eg. "<100,>1000,10-50,1234", any of them
+fn num_rules_part(name: &str, field: &str, matches: &getopts::Matches) -> Result<Option<String>, String> {
+    let value = match matches.opt_str(name) {
+        Some(v) => v,
+        None => return Ok(None)
     };
+    let rules = NumRule::parse_list(&value).map_err(|e| format!("[params] --{name}: {e}"))?;
+    let rules: Vec<String> = rules.iter().map(|r| r.to_string()).collect();
+    Ok(Some(format!("{field} in [{}]", rules.join(","))))
+}
 
-    let match_http_status_code = opt_int_split("mc", G_DEFAULT_MATCHES_STATUS_CODE, matches);
-    let match_resp_size = opt_int_some_parm("ms", matches);
-    let match_resp_line = opt_int_some_parm("ml", matches);
 
-    // empty
-    if match_regex.is_none() && match_http_status_code.is_none() &&
-        match_resp_size.is_none() && match_resp_line.is_none() {
-        return Ok(None)
+/// --fc, --fr, --fs, --fl, --fw, --fh, --fct and --ft are translated into an expression, --filter is added as is.
+/// Any of the status codes, the body rules or --filter drops the response.
+pub fn filter_params(matches: &getopts::Matches) -> Result<Option<FilterRules>, String> {
+    let mut body_parts = vec![];
+    if let Some(r) = regex_part("fr", "body", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(r) = regex_part("fh", "headers", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(r) = num_rules_part("fw", "words", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(part) = content_type_part("fct", matches) {
+        body_parts.push(part);
     }
+    if let Some(r) = num_rules_part("fl", "lines", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(r) = num_rules_part("fs", "size", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(r) = num_rules_part("ft", "ttfb", matches)? {
+        body_parts.push(r);
+    }
+    let body_op = if matches.opt_present("or-filter") { " || " } else { " && " };
 
-    Ok(Some(Matches {
-        regex: match_regex,
-        status_code: match_http_status_code,
-        line_num: match_resp_line,
-        resp_size: match_resp_size,
-        and_and_and: matches.opt_present("or-match").eq(&false),
+    let mut parts = vec![];
+    if let Some(codes) = matches.opt_str("fc") {
+        parts.push(status_rules("fc", &codes)?.unwrap_or_else(|| "true".to_string()));
+    }
+    if let Some(body) = join_parts(body_parts, body_op) {
+        parts.push(body);
+    }
+    if let Some(expr) = matches.opt_str("filter") {
+        parts.push(expr);
+    }
+    // empty
+    let source = match join_parts(parts, " || ") {
+        Some(s) => s,
+        None => return Ok(None)
+    };
+    Ok(Some(FilterRules {
+        expr: Some(Expr::parse(&source)?),
+        fingerprints: vec![],
     }))
 }
+
+/// --mc, --mr, --ms, --ml, --mw, --mh, --mct and --mt are translated into an expression, --match is added as is.
+/// The status codes, the body rules and --match must all agree.
+pub fn match_params(matches: &getopts::Matches) -> Result<Option<Expr>, String> {
+    let mut body_parts = vec![];
+    if let Some(r) = regex_part("mr", "body", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(r) = regex_part("mh", "headers", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(r) = num_rules_part("mw", "words", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(part) = content_type_part("mct", matches) {
+        body_parts.push(part);
+    }
+    if let Some(r) = num_rules_part("ms", "size", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(r) = num_rules_part("ml", "lines", matches)? {
+        body_parts.push(r);
+    }
+    if let Some(r) = num_rules_part("mt", "ttfb", matches)? {
+        body_parts.push(r);
+    }
+    let body_op = if matches.opt_present("or-match") { " || " } else { " && " };
+
+    let mut parts = vec![];
+    let codes = matches.opt_str("mc").unwrap_or_else(|| G_DEFAULT_MATCHES_STATUS_CODE.to_string());
+    if let Some(codes) = status_rules("mc", &codes)? {
+        parts.push(codes);
+    }
+    if let Some(body) = join_parts(body_parts, body_op) {
+        parts.push(body);
+    }
+    if let Some(expr) = matches.opt_str("match") {
+        parts.push(expr);
+    }
+    // empty
+    match join_parts(parts, " && ") {
+        Some(source) => Ok(Some(Expr::parse(&source)?)),
+        None => Ok(None)
+    }
+}
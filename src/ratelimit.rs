@@ -0,0 +1,167 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::time::{Instant, sleep_until};
+
+use crate::concurrency::{AdaptiveConcurrency, Slot};
+use crate::lru::{host_of, LruCache};
+use crate::throttle::HostBackoff;
+
+// how often the measured rate is refreshed
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Parse a rate, eg. "50", "50/s", "600/m" or "3600/h", into requests per second
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    let (n, unit) = s.trim().split_once('/').unwrap_or((s.trim(), "s"));
+    let seconds = match unit.trim() {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("[rate] invalid unit in \"{s}\", expected eg. 50/s, 600/m or 3600/h")),
+    };
+    match n.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(n / seconds),
+        _ => Err(format!("[rate] invalid rate \"{s}\", expected eg. 50/s, 600/m or 3600/h")),
+    }
+}
+
+/// Token bucket holding a single token, requests are spread evenly and
+/// never go over the rate, not even in a burst.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    // when the next request may go
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// `rate` requests per second
+    pub fn new(rate: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / rate),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait for the next slot, callers are served in the order they arrive
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        sleep_until(slot).await;
+    }
+}
+
+/// One limiter per host, created the first time a host is requested
+pub struct HostRateLimiter {
+    rate: f64,
+    limiters: Mutex<LruCache<Arc<RateLimiter>>>,
+}
+
+impl HostRateLimiter {
+    pub fn new(rate: f64, capacity: usize) -> Self {
+        Self {
+            rate,
+            limiters: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub async fn acquire(&self, url: &str) {
+        let host = match host_of(url) {
+            Some(host) => host,
+            None => return,
+        };
+        let limiter = {
+            let mut limiters = self.limiters.lock().unwrap();
+            match limiters.get(&host) {
+                Some(l) => l,
+                None => {
+                    let limiter = Arc::new(RateLimiter::new(self.rate));
+                    limiters.insert(&host, limiter.clone());
+                    limiter
+                }
+            }
+        };
+        limiter.acquire().await;
+    }
+}
+
+/// Requests sent per second, measured over the last second or so
+#[derive(Debug)]
+pub struct RateMeter {
+    sent: AtomicU64,
+    // start of the window, requests sent before it and the last measured rate
+    window: Mutex<(Instant, u64, f64)>,
+}
+
+impl Default for RateMeter {
+    fn default() -> Self {
+        Self {
+            sent: AtomicU64::new(0),
+            window: Mutex::new((Instant::now(), 0, 0.0)),
+        }
+    }
+}
+
+impl RateMeter {
+    pub fn record(&self) {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rate(&self) -> f64 {
+        let mut window = self.window.lock().unwrap();
+        let (start, sent_before, rate) = *window;
+        let elapsed = start.elapsed();
+        if elapsed < RATE_WINDOW {
+            return rate;
+        }
+        let sent = self.sent.load(Ordering::Relaxed);
+        let rate = (sent - sent_before) as f64 / elapsed.as_secs_f64();
+        *window = (Instant::now(), sent, rate);
+        rate
+    }
+}
+
+/// What a request waits on before it goes out, shared by the payloads and the calibration probes
+#[derive(Default)]
+pub struct Limits {
+    pub backoff: Option<Arc<HostBackoff>>,
+    pub host_rate: Option<HostRateLimiter>,
+    pub rate: Option<RateLimiter>,
+    pub concurrency: Option<Arc<AdaptiveConcurrency>>,
+    pub meter: Arc<RateMeter>,
+}
+
+/// Held from the send until the body is read
+pub struct Permit {
+    _slot: Option<Slot>,
+}
+
+impl Limits {
+    /// Wait for the host pause, the host and global rates and an in-flight slot.
+    /// None once the host was given up.
+    pub async fn acquire(&self, url: &str) -> Option<Permit> {
+        if let Some(backoff) = &self.backoff {
+            if !backoff.wait(url).await {
+                return None;
+            }
+        }
+        // the host slot first, a global one isn't held while waiting on a busy host
+        if let Some(limiter) = &self.host_rate {
+            limiter.acquire(url).await;
+        }
+        if let Some(limiter) = &self.rate {
+            limiter.acquire().await;
+        }
+        let slot = match &self.concurrency {
+            Some(c) => Some(c.acquire().await),
+            None => None,
+        };
+        self.meter.record();
+        Some(Permit { _slot: slot })
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+
+use reqwest::{Client, Method, Request};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::wordlist::Payload;
+
+pub const DEFAULT_REQUEST_PROTO: &str = "https";
+
+// recomputed by the http client, Host only when the url is made of it
+const SKIP_HEADERS: [&str; 3] = ["host", "content-length", "connection"];
+
+/// Parse a `-H "Name: value"` option
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    match header.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("invalid header \"{header}\", for example: -H \"Name: value\"")),
+    }
+}
+
+// the head ends at the first blank line, CRLF or LF. The body is kept as is
+fn split_head(raw: &[u8]) -> (&[u8], &[u8]) {
+    let mut start = 0;
+    while let Some(end) = raw[start..].iter().position(|&b| b == b'\n') {
+        let line = &raw[start..start + end];
+        if line.is_empty() || line == b"\r" {
+            return (&raw[..start], &raw[start + end + 1..]);
+        }
+        start += end + 1;
+    }
+    (raw, &[])
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// Payload::fill for the body, it may not be text
+fn fill_bytes(payload: &Payload, body: &[u8]) -> Vec<u8> {
+    let mut filled = body.to_vec();
+    for (keyword, value) in payload.words.iter() {
+        let mut replaced = Vec::with_capacity(filled.len());
+        let mut rest = filled.as_slice();
+        while let Some(i) = find_bytes(rest, keyword.as_bytes()) {
+            replaced.extend_from_slice(&rest[..i]);
+            replaced.extend_from_slice(value.as_bytes());
+            rest = &rest[i + keyword.len()..];
+        }
+        replaced.extend_from_slice(rest);
+        filled = replaced;
+    }
+    filled
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RequestTemplate {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    // raw bytes, a file upload needn't be utf-8
+    pub body: Option<Vec<u8>>,
+}
+
+impl RequestTemplate {
+    pub fn from_url(url: &str) -> Self {
+        Self {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: None,
+        }
+    }
+
+    pub fn from_file(path: &str, proto: &str, url: Option<&str>) -> Result<Self, String> {
+        match fs::read(path) {
+            Ok(raw) => Self::from_raw(&raw, proto, url),
+            Err(e) => Err(format!("[request] {path} read failed. {e}")),
+        }
+    }
+
+    /// Parse a raw http request, as copied from Burp. The url is made of the Host
+    /// header and the path, unless the request line holds a full url or `url` (-u)
+    /// replaces it: Host is then sent as written, eg. FUZZ in it for virtual hosts.
+    pub fn from_raw(raw: impl AsRef<[u8]>, proto: &str, url: Option<&str>) -> Result<Self, String> {
+        let (head, body) = split_head(raw.as_ref());
+        let head = std::str::from_utf8(head)
+            .map_err(|_| "[request] the request line and headers must be utf-8.".to_string())?;
+        let mut lines = head.lines();
+        let request_line = lines.next().unwrap_or_default().trim();
+        let parts: Vec<&str> = request_line.split_whitespace().collect();
+        if parts.len() < 2 {
+            return Err(format!("[request] invalid request line: \"{request_line}\""));
+        }
+        let (method, path) = (parts[0], parts[1]);
+        let absolute = path.starts_with("http://") || path.starts_with("https://");
+        let keep_host = absolute || url.is_some();
+
+        let mut host = None;
+        let mut headers = vec![];
+        for line in lines {
+            let (name, value) = match line.split_once(':') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => return Err(format!("[request] invalid header: \"{line}\"")),
+            };
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.to_string());
+                if keep_host {
+                    headers.push((name.to_string(), value.to_string()));
+                    continue;
+                }
+            }
+            if SKIP_HEADERS.contains(&name.to_lowercase().as_str()) {
+                continue;
+            }
+            headers.push((name.to_string(), value.to_string()));
+        }
+
+        let url = match (url, host) {
+            (Some(url), _) => url.to_string(),
+            _ if absolute => path.to_string(),
+            (None, Some(host)) => format!("{proto}://{host}{path}"),
+            (None, None) => return Err("[request] missing Host header.".to_string()),
+        };
+        Ok(Self {
+            method: method.to_string(),
+            url,
+            headers,
+            body: if body.is_empty() { None } else { Some(body.to_vec()) },
+        })
+    }
+
+    /// Check the method and headers before the scan starts,
+    /// keywords are valid token characters so the template itself must pass.
+    pub fn validate(&self) -> Result<(), String> {
+        if Method::from_bytes(self.method.as_bytes()).is_err() {
+            return Err(format!("invalid method \"{}\"", self.method));
+        }
+        for (name, value) in self.headers.iter() {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(format!("invalid header name \"{name}\""));
+            }
+            if HeaderValue::from_str(value).is_err() {
+                return Err(format!("invalid header value \"{name}: {value}\""));
+            }
+        }
+        Ok(())
+    }
+
+    /// Add or replace a header, names are case-insensitive
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|(k, _)| !k.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn contains(&self, keyword: &str) -> bool {
+        self.method.contains(keyword)
+            || self.url.contains(keyword)
+            || self.headers.iter().any(|(k, v)| k.contains(keyword) || v.contains(keyword))
+            || self.body.as_ref().and_then(|b| find_bytes(b, keyword.as_bytes())).is_some()
+    }
+
+    pub fn fill(&self, payload: &Payload) -> Self {
+        Self {
+            method: payload.fill(&self.method),
+            url: payload.fill(&self.url),
+            headers: self.headers.iter()
+                .map(|(k, v)| (payload.fill(k), payload.fill(v)))
+                .collect(),
+            body: self.body.as_ref().map(|b| fill_bytes(payload, b)),
+        }
+    }
+
+    pub fn build(&self, client: &Client) -> Result<Request, String> {
+        let method = Method::from_bytes(self.method.as_bytes())
+            .map_err(|_| format!("invalid method \"{}\"", self.method))?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers.iter() {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| format!("invalid header name \"{name}\""))?;
+            let header_value = HeaderValue::from_str(value)
+                .map_err(|_| format!("invalid header value \"{name}: {value}\""))?;
+            headers.append(header_name, header_value);
+        }
+        let mut builder = client.request(method, &self.url).headers(headers);
+        if let Some(body) = &self.body {
+            builder = builder.body(body.clone());
+        }
+        builder.build().map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::common::G_DEFAULT_RETRY_DELAY_MS;
+use crate::error::ErrorClass;
+
+// the backoff stops doubling here
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Comma separated error classes, "all" or "none"
+pub fn parse_classes(s: &str) -> Result<Vec<ErrorClass>, String> {
+    match s.trim().to_lowercase().as_str() {
+        "all" => return Ok(ErrorClass::all()),
+        "none" => return Ok(vec![]),
+        _ => {}
+    }
+    s.split(',').filter(|c| !c.trim().is_empty()).map(|c| c.parse()).collect()
+}
+
+/// Which failed requests are sent again and how long to wait before.
+/// DNS and TLS failures won't go away on their own, they aren't retried by default.
+/// A refused connection is often a full backlog, it is.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub classes: Vec<ErrorClass>,
+    // first delay, doubled on each retry
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            classes: vec![ErrorClass::Timeout, ErrorClass::Refused, ErrorClass::Reset, ErrorClass::Body, ErrorClass::Other],
+            base_delay: Duration::from_millis(G_DEFAULT_RETRY_DELAY_MS),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn retries(&self, class: &ErrorClass) -> bool {
+        self.classes.contains(class)
+    }
+
+    /// Delay before retry `n`, 1 for the first. Exponential with jitter,
+    /// between half and the whole of base * 2^(n-1)
+    pub fn delay(&self, n: usize) -> Duration {
+        let exp = (n.max(1) - 1).min(16) as u32;
+        let cap = self.base_delay.saturating_mul(1 << exp).min(MAX_RETRY_DELAY);
+        let half = cap / 2;
+        half + half.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
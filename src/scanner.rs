@@ -1,38 +1,105 @@
-use std::sync::Arc;
-use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
-use futures::{stream, StreamExt};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use futures::{Stream, stream, StreamExt};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use rand::{Rng, thread_rng};
 use reqwest::{Client, header, redirect};
 use reqwest::dns::Resolve;
-use reqwest::header::HeaderMap;
+use reqwest::header::{HeaderMap, HeaderValue};
 use tokio::time::Instant;
 use trust_dns_resolver::system_conf::read_system_conf;
 
-use crate::{G_LOOP_BREAK, G_RESPONSE, G_STATS, HttpResp};
+use crate::calibration::{calibrate, HostBaselines};
+use crate::checkpoint::{Checkpoint, CHECKPOINT_INTERVAL};
+use crate::concurrency::{AdaptiveConcurrency, is_overload};
 use crate::data_handler::ListenData;
 use crate::data_type::*;
-use crate::error::{stats_code_inc, stats_err_inc};
+use crate::error::{classify, ErrorClass, stats_code_inc, stats_err_inc};
+use crate::lru::HOST_CACHE_SIZE;
+use crate::ratelimit::{HostRateLimiter, Limits, RateLimiter, RateMeter};
+use crate::sink::{open_extract_sink, open_sink, OutputFormat, ResultSink, TerminalSink};
+use crate::throttle::{HostBackoff, is_throttled, MAX_PAYLOAD_THROTTLED, parse_retry_after, Verdict};
 use crate::dns_preheat::TrustDnsResolver;
 
-pub struct Scanner<'a> {
-    options: &'a Options<'a>,
+// how often a pause checks the stop flag, it is a plain bool (Scanner::stop_handle)
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+// resolves once the scan is stopped
+async fn stopped(running: &AtomicBool) {
+    while running.load(Ordering::SeqCst) {
+        tokio::time::sleep(STOP_POLL).await;
+    }
+}
+
+// what became of a payload, one cut short by a stop isn't counted done
+enum Outcome {
+    Done(Option<Box<HttpResp>>),
+    Stopped,
+}
+
+fn jobs_len<T>(sender: &tokio::sync::mpsc::Sender<T>) -> usize {
+    sender.max_capacity() - sender.capacity()
+}
+
+pub struct Scanner {
+    params: Params,
+    stats: Arc<ScanStats>,
+    // cleared to stop issuing new requests
+    running: Arc<AtomicBool>,
+    // extra sinks of library users, -o files are opened by the scan
+    sinks: Vec<Box<dyn ResultSink>>,
+    // requests sent, for the rate on the stats bar
+    meter: Arc<RateMeter>,
+    concurrency: Option<Arc<AdaptiveConcurrency>>,
     mpg: MultiProgress,
 }
 
 
-impl<'a> Scanner<'a> {
-    pub fn new(options: &'a Options) -> Self {
+impl Scanner {
+    pub fn new(params: Params) -> Self {
+        let mpg = if params.terminal {
+            MultiProgress::new()
+        } else {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        };
+        let concurrency = params.auto_concurrency
+            .map(|(min, max)| Arc::new(AdaptiveConcurrency::new(params.concurrent_num, min, max)));
         Self {
-            options,
-            mpg: MultiProgress::new(),
+            params,
+            stats: Arc::new(ScanStats::new()),
+            running: Arc::new(AtomicBool::new(true)),
+            sinks: vec![],
+            meter: Arc::new(RateMeter::default()),
+            concurrency,
+            mpg,
         }
     }
 
+    pub fn stats(&self) -> Arc<ScanStats> {
+        self.stats.clone()
+    }
+
+    /// Store false to stop the scan, in-flight requests are still processed.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.running.clone()
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn ResultSink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Run the scan in the background and stream its events,
+    /// the last one is always `ScanEvent::Finished`.
+    pub fn run(self) -> impl Stream<Item=ScanEvent> + Unpin {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(self.params.queue_size);
+        tokio::spawn(self.start(tx));
+        stream::poll_fn(move |cx| rx.poll_recv(cx))
+    }
+
     pub fn install_pb(&self, deps: u64) -> (ProgressBar, ProgressBar, ProgressBar) {
         let http_spinner_style = ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {wide_msg}")
             .unwrap()
@@ -40,7 +107,12 @@ impl<'a> Scanner<'a> {
         let stats_spinner_style = ProgressStyle::with_template("{prefix:.bold.dim} {spinner} {wide_msg} elapsed: {elapsed_precise}")
             .unwrap()
             .tick_chars("⠁⠂⠄⡀ ");
-        let pb = self.mpg.add(ProgressBar::new(deps));
+        // unknown length when the wordlist comes from stdin
+        let pb = if deps > 0 {
+            self.mpg.add(ProgressBar::new(deps))
+        } else {
+            self.mpg.add(ProgressBar::new_spinner())
+        };
         let status_bar = self.mpg.insert_before(&pb, ProgressBar::new(deps));
         status_bar.set_prefix("HTTP");
         status_bar.set_style(http_spinner_style);
@@ -52,34 +124,50 @@ impl<'a> Scanner<'a> {
         stats_bar.set_position(0);
         status_bar.set_message("waiting...".to_string());
         stats_bar.set_message("waiting...".to_string());
-        if !self.options.params.print_state {
+        if !self.params.print_state {
             status_bar.finish_and_clear();
             stats_bar.finish_and_clear();
         }
         (pb, status_bar, stats_bar)
     }
 
-    pub fn refresh_pb(&self, status_bar: &ProgressBar, stats_bar: &ProgressBar) {
-        if !self.options.params.print_state {
+    pub fn refresh_pb(&self, status_bar: &ProgressBar, stats_bar: &ProgressBar, jobs_len: usize) {
+        if !self.params.print_state {
             return;
         }
+        let stats = &self.stats;
         status_bar.set_message(format!("200: {}, 404: {}, 301: {}, 302: {}, 403: {}, 401: {}, 500: {}, 502: {}",
-                                       &G_STATS.get(&Stats::C200).unwrap().to_string(),
-                                       &G_STATS.get(&Stats::C404).unwrap().to_string(),
-                                       &G_STATS.get(&Stats::C301).unwrap().to_string(),
-                                       &G_STATS.get(&Stats::C302).unwrap().to_string(),
-                                       &G_STATS.get(&Stats::C403).unwrap().to_string(),
-                                       &G_STATS.get(&Stats::C401).unwrap().to_string(),
-                                       &G_STATS.get(&Stats::C500).unwrap().to_string(),
-                                       &G_STATS.get(&Stats::C502).unwrap().to_string(),
+                                       &stats.get(&Stats::C200).to_string(),
+                                       &stats.get(&Stats::C404).to_string(),
+                                       &stats.get(&Stats::C301).to_string(),
+                                       &stats.get(&Stats::C302).to_string(),
+                                       &stats.get(&Stats::C403).to_string(),
+                                       &stats.get(&Stats::C401).to_string(),
+                                       &stats.get(&Stats::C500).to_string(),
+                                       &stats.get(&Stats::C502).to_string(),
         ));
-        let jobs_len = G_RESPONSE.read().unwrap().queue.len();
-        stats_bar.set_message(format!("Hits: {}, , Jobs: {}, TO: {}, IO: {}, DNS: {}",
-                                      &G_STATS.get(&Stats::Hits).unwrap().to_string(),
+        let rate = match self.params.rate {
+            Some(max) => format!("{:.1}/s (max {max:.1}/s)", self.meter.rate()),
+            None => format!("{:.1}/s", self.meter.rate()),
+        };
+        let rate = match &self.concurrency {
+            Some(c) => format!("{rate}, Concurrency: {}", c.limit()),
+            None => rate,
+        };
+        stats_bar.set_message(format!("Hits: {}, Rate: {}, Jobs: {}, TO: {}, IO: {}, DNS: {}, Refused: {}, Reset: {}, TLS: {}, Proxy: {}, Redirect: {}, Decode: {}, 429/503: {}",
+                                      &stats.get(&Stats::Hits).to_string(),
+                                      rate,
                                       jobs_len,
-                                      &G_STATS.get(&Stats::TimeOut).unwrap().to_string(),
-                                      &G_STATS.get(&Stats::IOErr).unwrap().to_string(),
-                                      &G_STATS.get(&Stats::DNSErr).unwrap().to_string(),
+                                      &stats.get(&Stats::TimeOut).to_string(),
+                                      &stats.get(&Stats::IOErr).to_string(),
+                                      &stats.get(&Stats::DNSErr).to_string(),
+                                      &stats.get(&Stats::ConnRefused).to_string(),
+                                      &stats.get(&Stats::ConnReset).to_string(),
+                                      &stats.get(&Stats::TlsErr).to_string(),
+                                      &stats.get(&Stats::ProxyErr).to_string(),
+                                      &stats.get(&Stats::RedirectErr).to_string(),
+                                      &stats.get(&Stats::DecodeErr).to_string(),
+                                      &stats.get(&Stats::Throttled).to_string(),
         ));
         stats_bar.inc(1);
         status_bar.inc(1);
@@ -87,9 +175,17 @@ impl<'a> Scanner<'a> {
 
     pub fn client_build(&self) -> Option<Client> {
         let mut headers = HeaderMap::new();
-        let options = self.options;
-        headers.insert(header::USER_AGENT, options.params.user_agent.parse().unwrap());
-        headers.insert(header::ACCEPT_LANGUAGE, "en-US,en;q=0.9".parse().unwrap());
+        // -H values take precedence over these defaults
+        match HeaderValue::from_str(&self.params.user_agent) {
+            Ok(v) => {
+                headers.insert(header::USER_AGENT, v);
+            }
+            Err(e) => {
+                println!("invalid user-agent. {e}");
+                return None;
+            }
+        }
+        headers.insert(header::ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.9"));
 
         let mut client = Client::builder()
             .use_rustls_tls()
@@ -97,8 +193,8 @@ impl<'a> Scanner<'a> {
             .http2_initial_stream_window_size(Some(65535)) // Increase the initial HTTP/2 stream window size for better throughput
             .http2_initial_connection_window_size(Some(1048576)) // Increase the initial HTTP/2 connection window size for better throughput
             .danger_accept_invalid_certs(true)
-            .timeout(Duration::from_secs(options.params.request_timeout))
-            .connect_timeout(Duration::from_secs(options.params.request_timeout))
+            .timeout(Duration::from_secs(self.params.request_timeout))
+            .connect_timeout(Duration::from_secs(self.params.request_timeout))
             .default_headers(headers)
             .http1_only()
             .trust_dns(true)
@@ -107,18 +203,18 @@ impl<'a> Scanner<'a> {
             .deflate(true)
             .tcp_nodelay(true)
             .tcp_keepalive(None);
-        if options.params.dns_try {
+        if self.params.dns_try {
             client = client.dns_resolver(Arc::new(TrustDnsResolver::new().unwrap()));
         }
-        if options.params.follow_redirect == 0 {
+        if self.params.follow_redirect == 0 {
             client = client.redirect(redirect::Policy::none())
         } else {
-            client = client.redirect(redirect::Policy::limited(options.params.follow_redirect))
+            client = client.redirect(redirect::Policy::limited(self.params.follow_redirect))
         }
-        if !options.params.proxy_server.is_empty() {
-            match reqwest::Proxy::all(options.params.proxy_server.clone()) {
+        if !self.params.proxy_server.is_empty() {
+            match reqwest::Proxy::all(self.params.proxy_server.clone()) {
                 Ok(mut p) => {
-                    p = p.basic_auth(&options.params.proxy_user, &options.params.proxy_pass);
+                    p = p.basic_auth(&self.params.proxy_user, &self.params.proxy_pass);
                     client = client.proxy(p);
                 }
                 Err(e) => {
@@ -127,131 +223,395 @@ impl<'a> Scanner<'a> {
                 }
             }
         }
-        Some(client.build().unwrap())
+        match client.build() {
+            Ok(c) => Some(c),
+            Err(e) => {
+                println!("{e}");
+                None
+            }
+        }
     }
 
-    pub async fn start(self) {
-        let options = self.options.clone();
+    pub async fn start(mut self, events: tokio::sync::mpsc::Sender<ScanEvent>) {
+        let start = Instant::now();
+        let params = self.params.clone();
+        let stats = self.stats.clone();
+        let running = self.running.clone();
         use std::sync::mpsc::channel;
         let (pr_tx, pr_rx): (Sender<String>, Receiver<String>) = channel();
+        let pr_rx = Mutex::new(pr_rx);
 
-        let mut listen_data = ListenData::new(pr_tx, options.params.scan_mode);
-        if let Some(opt) = options.params.script_option {
+        let mut listen_data = ListenData::new(pr_tx.clone(), params.scan_mode.clone(), stats.clone());
+        listen_data.set_terminal(params.terminal);
+        listen_data.use_events(events.clone());
+        if let Some(opt) = params.script_option.clone() {
             listen_data.use_script(opt);
         }
-        if let Some(p) =&options.params.result_file {
-            listen_data.save_as(p)
+        if params.cluster_size > 0 {
+            listen_data.use_clusters(params.cluster_size);
         }
-        let matches = options.params.custom_matches.clone();
-        let filters = options.params.custom_filters.clone();
-        listen_data.handler( matches, filters);
-
-        let client = self.client_build();
-        let client = match client {
+        listen_data.use_extract(params.extract.clone());
+        if params.terminal {
+            let terminal = TerminalSink::new(pr_tx, params.scan_mode.clone()).with_columns(params.columns.clone());
+            listen_data.add_sink(Box::new(terminal));
+        }
+        for (path, format) in params.outputs.iter() {
+            match open_sink(path, format) {
+                Ok(sink) => listen_data.add_sink(sink),
+                Err(e) => {
+                    let _ = events.send(ScanEvent::Error(format!("{path} open failed. {e}"))).await;
+                    return;
+                }
+            }
+        }
+        if let Some(path) = &params.extract_output {
+            match open_extract_sink(path) {
+                Ok(sink) => listen_data.add_sink(Box::new(sink)),
+                Err(e) => {
+                    let _ = events.send(ScanEvent::Error(format!("{path} open failed. {e}"))).await;
+                    return;
+                }
+            }
+        }
+        if let Some(checkpoint) = &params.checkpoint {
+            // checkpoint copy of the hits
+            let path = Checkpoint::hits_path(&checkpoint.id);
+            match open_sink(&path, &OutputFormat::Text) {
+                Ok(sink) => listen_data.add_sink(sink),
+                Err(e) => log::error!("{path} open failed. {e}"),
+            }
+        }
+        for sink in self.sinks.drain(..) {
+            listen_data.add_sink(sink);
+        }
+        let client = match self.client_build() {
             Some(c) => c,
-            None => panic!("http client failed to initialize.")
+            None => {
+                let _ = events.send(ScanEvent::Error("http client failed to initialize.".to_string())).await;
+                return;
+            }
+        };
+        // the calibration probes wait on them too
+        let limits = Arc::new(Limits {
+            backoff: params.backoff
+                .map(|(max_rounds, action)| Arc::new(HostBackoff::new(max_rounds, action, HOST_CACHE_SIZE))),
+            host_rate: params.host_rate.map(|rate| HostRateLimiter::new(rate, HOST_CACHE_SIZE)),
+            rate: params.rate.map(RateLimiter::new),
+            concurrency: self.concurrency.clone(),
+            meter: self.meter.clone(),
+        });
+        let matches = params.custom_matches.clone();
+        let mut filters = params.custom_filters.clone();
+        if params.auto_calibration {
+            let fingerprints = calibrate(&client, &limits, &stats, &params.request, &params.wordlist.keywords()).await;
+            filters.get_or_insert_with(FilterRules::default).fingerprints.extend(fingerprints);
+        }
+        // bounded, a slow matcher or script holds back the requests instead of piling up responses
+        let (resp_tx, resp_rx) = tokio::sync::mpsc::channel::<HttpResp>(params.queue_size);
+        listen_data.handler(resp_rx, params.worker_num, matches, filters);
+
+        let payloads = match params.wordlist.payloads() {
+            Ok(p) => p,
+            Err(e) => {
+                log::error!("wordlist read failed. {e}");
+                let _ = events.send(ScanEvent::Error(format!("wordlist read failed. {e}"))).await;
+                return;
+            }
         };
-        let bodies = stream::iter(options.params.wordlist).map(|payload| {
+        // checkpoint state, saved periodically and when the scan is interrupted
+        let checkpoint = Arc::new(Mutex::new(params.checkpoint.clone()));
+        let progress = params.checkpoint.as_ref().map(|c| c.progress.clone()).unwrap_or_default();
+        if let (true, Some(c)) = (params.resumed, &params.checkpoint) {
+            c.restore_stats(&stats);
+        }
+        let last_save = Mutex::new(Instant::now());
+        if params.terminal {
+            // first signal stops new requests and lets the queue drain, the second one exits
+            let checkpoint = checkpoint.clone();
+            let running = running.clone();
+            let stats = stats.clone();
+            let _ = ctrlc::set_handler(move || {
+                if running.swap(false, Ordering::SeqCst) {
+                    eprintln!("\nstopping, waiting for in-flight requests. Ctrl-C again to exit now.");
+                    return;
+                }
+                if let Ok(mut checkpoint) = checkpoint.try_lock() {
+                    if let Some(checkpoint) = checkpoint.as_mut() {
+                        if checkpoint.save(&stats).is_ok() {
+                            eprintln!("\ncontinue with: --resume {}", checkpoint.id);
+                        }
+                    }
+                }
+                std::process::exit(130);
+            });
+        }
+
+        let baselines = params.host_calibration
+            .then(|| Arc::new(HostBaselines::new(HOST_CACHE_SIZE)));
+        let stop = running.clone();
+        let done = progress.clone();
+        let payloads = payloads.enumerate()
+            .skip(progress.position as usize)
+            .filter(move |(index, _)| !done.is_done(*index as u64))
+            .take_while(move |_| stop.load(Ordering::SeqCst));
+        let bodies = stream::iter(payloads).map(|(index, payload)| {
             let client = client.clone();
-            let fuzz_url = options.params.fuzz_url.replace("FUZZ", &payload);
-            let match_status = match options.params.custom_matches.clone() {
-                Some(s) => s.status_code,
-                None=> None,
-            };
-            let filter_status = match options.params.custom_filters.clone() {
-                Some(s) => s.status_code,
-                None=> None,
-            };
+            let stats = stats.clone();
+            let request = params.request.fill(&payload);
+            let baselines = baselines.clone();
+            let limits = limits.clone();
+            let running = running.clone();
+            let payload = payload.to_string();
+            let fuzz_url = request.url.clone();
+            let request_retries = params.request_retries;
+            let retry_policy = params.retry_policy.clone();
+            let proxied = !params.proxy_server.is_empty();
+            let match_expr = params.custom_matches.clone();
+            let filter_expr = params.custom_filters.as_ref().and_then(|f| f.expr.clone());
 
-            tokio::spawn(async move {
-                for _ in 0..options.params.request_retries {
+            let handle = tokio::spawn(async move {
+                let template = request;
+                let request = match template.build(&client) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        log::error!("{} {}", fuzz_url, e);
+                        return Outcome::Done(None);
+                    }
+                };
+                // throttled responses are sent again without using up a retry, up to a limit
+                let mut attempt = 0;
+                let mut throttled = 0;
+                while attempt < request_retries {
+                    // a stop ends the backoff pauses and the re-sends too
+                    let permit = tokio::select! {
+                        biased;
+                        _ = stopped(&running) => return Outcome::Stopped,
+                        permit = limits.acquire(&fuzz_url) => permit,
+                    };
+                    let permit = match permit {
+                        Some(permit) => permit,
+                        None => {
+                            stats.inc(&Stats::Dropped);
+                            return Outcome::Done(None);
+                        }
+                    };
+                    attempt += 1;
                     let start = Instant::now();
-                    let resp = client.get(&fuzz_url).send().await;
-                    match resp {
+                    // the body is always in memory, cloning can't fail
+                    let resp = client.execute(request.try_clone().unwrap()).await;
+                    let class = match resp {
                         Ok(r) => {
+                            // reqwest resolves once the headers are read
+                            let ttfb = start.elapsed();
+                            // counted once the request is over, a body cut short is a failure too
+                            let done = |failed: bool| if let Some(c) = &limits.concurrency {
+                                c.record(failed);
+                            };
                             let status = r.status().as_u16();
-                            stats_code_inc(&status);
+                            if let Some(backoff) = &limits.backoff {
+                                let retry_after = r.headers().get(header::RETRY_AFTER)
+                                    .and_then(|v| v.to_str().ok());
+                                if is_throttled(status, retry_after.is_some()) {
+                                    stats.inc(&Stats::Throttled);
+                                    let retry_after = retry_after.and_then(|v| parse_retry_after(v, SystemTime::now()));
+                                    match backoff.throttled(&fuzz_url, retry_after) {
+                                        Verdict::Retry if throttled < MAX_PAYLOAD_THROTTLED => {
+                                            done(false);
+                                            throttled += 1;
+                                            attempt -= 1;
+                                            continue;
+                                        }
+                                        // the response goes through the matchers like any other
+                                        Verdict::Retry => {
+                                            log::warn!("{fuzz_url} throttled {MAX_PAYLOAD_THROTTLED} times, giving up");
+                                        }
+                                        Verdict::DropHost => {
+                                            done(false);
+                                            stats.inc(&Stats::Dropped);
+                                            return Outcome::Done(None);
+                                        }
+                                        Verdict::Abort => {
+                                            done(false);
+                                            running.store(false, Ordering::SeqCst);
+                                            return Outcome::Stopped;
+                                        }
+                                    }
+                                } else {
+                                    backoff.success(&fuzz_url);
+                                }
+                            }
+                            stats_code_inc(&stats, &status);
                             // Prioritize invalid states
-                            // This will discard excluded prints
-                            if let Some(s) = &filter_status {
-                                if s.contains(&status) {
-                                    return None;
+                            // This will discard excluded prints, the body is not downloaded
+                            if let Some(expr) = &filter_expr {
+                                if expr.eval_status(status) == Some(true) {
+                                    done(false);
+                                    return Outcome::Done(None);
                                 }
                             }
-                            if let Some(s) = &match_status {
-                                if !s.contains(&status) {
-                                    return None;
+                            if let Some(expr) = &match_expr {
+                                if expr.eval_status(status) == Some(false) {
+                                    done(false);
+                                    return Outcome::Done(None);
                                 }
                             }
                             let real_url = r.url().to_string();
                             let remote_addr = r.remote_addr();
+                            let header_str = |name| r.headers().get(name)
+                                .and_then(|v| v.to_str().ok())
+                                .map(|v| v.to_string());
+                            let content_type = header_str(header::CONTENT_TYPE);
+                            let location = header_str(header::LOCATION);
+                            let server = header_str(header::SERVER);
+                            let headers = r.headers().iter()
+                                .map(|(k, v)| (k.to_string(), String::from_utf8_lossy(v.as_bytes()).to_string()))
+                                .collect();
                             match &r.bytes().await {
                                 Ok(data) => {
+                                    done(false);
                                     let duration = start.elapsed();
-                                    return Some(HttpResp {
+                                    // the baseline probes need slots of their own
+                                    drop(permit);
+                                    if let Some(baselines) = &baselines {
+                                        let shape = Fingerprint::of(status, data);
+                                        let baseline = baselines.get(&client, &limits, &stats, &template).await;
+                                        if baseline.iter().any(|f| f.matches(&shape)) {
+                                            return Outcome::Done(None);
+                                        }
+                                    }
+                                    return Outcome::Done(Some(Box::new(HttpResp {
+                                        payload,
                                         status,
                                         url: real_url,
                                         html: data.to_vec(),
+                                        ttfb,
                                         duration,
                                         remote_addr,//Real ip acquisition, needs some improvement
-                                    });
+                                        content_type,
+                                        location,
+                                        server,
+                                        headers,
+                                    })));
                                 }
                                 Err(e) => {
+                                    done(is_overload(&stats_err_inc(&stats, e, proxied)));
                                     log::warn!("{}", e.to_string());
+                                    ErrorClass::Body
                                 }
                             }
                         }
                         Err(e) => {
-                            stats_err_inc(&e);
+                            let stat = stats_err_inc(&stats, &e, proxied);
+                            if let Some(c) = &limits.concurrency {
+                                c.record(is_overload(&stat));
+                            }
                             log::error!("{} {}", fuzz_url, e.to_string());
+                            classify(&e)
                         }
+                    };
+                    // not held while waiting
+                    drop(permit);
+                    if attempt >= request_retries || !retry_policy.retries(&class) {
+                        break;
+                    }
+                    stats.inc(&class.retry_stat());
+                    tokio::select! {
+                        biased;
+                        _ = stopped(&running) => return Outcome::Stopped,
+                        _ = tokio::time::sleep(retry_policy.delay(attempt)) => {}
                     }
                 }
-                None
-            })
-        }).buffer_unordered(options.params.concurrent_num);
+                Outcome::Done(None)
+            });
+            async move { (index as u64, handle.await) }
+        });
+        // the adaptive limit decides, up to its max
+        let in_flight = params.auto_concurrency.map_or(params.concurrent_num, |(_, max)| max);
+        let bodies = bodies.buffer_unordered(in_flight);
 
-        let deps = options.params.wordlist_len as u64;
+        let deps = params.wordlist_len as u64;
         let (pb, status_bar, stats_bar) = self.install_pb(deps);
+        pb.set_position(progress.count());
         #[cfg(target_arch = "x86_64")]
         use std::arch::x86_64::_rdtsc;
-        bodies.for_each(|resp| async {
-            pb.inc(1);
-            if let Ok(msg) = pr_rx.try_recv() {
-                pb.println(msg);
-            }
-            if self.options.params.wordlist_len > 100000 {
-                // everybody's busy
-                unsafe {
-                    let unr = if cfg!(target_arch = "x86_64") {
-                        _rdtsc() % 500
-                    }else{
-                        thread_rng().gen::<u64>() % 500
-                    };
-                    if unr == 0 {
-                        self.refresh_pb(&status_bar, &stats_bar);
+        bodies.for_each(|(index, resp)| {
+            // not sent, a resumed scan starts again from it
+            let stopped = matches!(resp, Ok(Outcome::Stopped));
+            if let (false, Some(checkpoint)) = (stopped, checkpoint.lock().unwrap().as_mut()) {
+                checkpoint.progress.complete(index);
+                let mut last_save = last_save.lock().unwrap();
+                if last_save.elapsed().as_secs() >= CHECKPOINT_INTERVAL {
+                    if let Err(e) = checkpoint.save(&stats) {
+                        log::error!("checkpoint save failed. {e}");
                     }
+                    *last_save = Instant::now();
                 }
-            }else {
-                self.refresh_pb(&status_bar, &stats_bar);
             }
+            async {
+                if matches!(resp, Ok(Outcome::Stopped)) {
+                    return;
+                }
+                stats.inc(&Stats::Done);
+                pb.inc(1);
+                if let Ok(msg) = pr_rx.lock().unwrap().try_recv() {
+                    pb.println(msg);
+                }
+                if params.wordlist_len == 0 || params.wordlist_len > 100000 {
+                    // everybody's busy
+                    unsafe {
+                        let unr = if cfg!(target_arch = "x86_64") {
+                            _rdtsc() % 500
+                        }else{
+                            thread_rng().gen::<u64>() % 500
+                        };
+                        if unr == 0 {
+                            self.refresh_pb(&status_bar, &stats_bar, jobs_len(&resp_tx));
+                        }
+                    }
+                }else {
+                    self.refresh_pb(&status_bar, &stats_bar, jobs_len(&resp_tx));
+                }
 
-            if let Ok(Some(resp)) = resp {
-                let mut resp_write = G_RESPONSE.write().unwrap();
-                resp_write.enqueue(resp);
+                if let Ok(Outcome::Done(Some(resp))) = resp {
+                    if resp_tx.send(*resp).await.is_err() {
+                        log::error!("response workers exited.");
+                    }
+                }
             }
         }).await;
+        let interrupted = !running.load(Ordering::SeqCst);
         //send over signal
-        G_LOOP_BREAK.store(false, Ordering::SeqCst);
-        listen_data.waiting();
+        running.store(false, Ordering::SeqCst);
+        drop(resp_tx);
+        let clusters = listen_data.clusters();
+        // workers may block on a slow event consumer
+        let _ = tokio::task::spawn_blocking(move || listen_data.waiting()).await;
         // Completion of final work
-        while let Ok(msg) = pr_rx.try_recv() {
+        while let Ok(msg) = pr_rx.lock().unwrap().try_recv() {
             pb.println(msg);
-            self.refresh_pb(&status_bar, &stats_bar);
+            self.refresh_pb(&status_bar, &stats_bar, 0);
         }
-        if self.options.params.scan_mode == ScanMode::Debug {
+        if params.terminal && params.scan_mode == ScanMode::Debug {
             sleep(Duration::from_secs(3));
         }
         pb.finish_and_clear();
+
+        if let Some(checkpoint) = checkpoint.lock().unwrap().as_mut() {
+            if interrupted {
+                if let Err(e) = checkpoint.save(&stats) {
+                    log::error!("checkpoint save failed. {e}");
+                }
+            } else {
+                // finished, nothing left to resume
+                checkpoint.remove();
+            }
+        }
+        let _ = events.send(ScanEvent::Finished(ScanSummary {
+            stats: stats.snapshot(),
+            elapsed: start.elapsed(),
+            interrupted,
+            clusters: clusters.map(|c| c.lock().unwrap().suppressed()).unwrap_or_default(),
+            dropped_hosts: limits.backoff.as_ref().map(|b| b.dropped()).unwrap_or_default(),
+        })).await;
     }
-}
\ No newline at end of file
+}
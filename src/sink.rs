@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+
+use console::Style;
+use serde::Serialize;
+
+use crate::data_type::{ScanHit, ScanMode};
+use crate::extract::{format_values, group};
+
+/// Receives every hit of a scan, `-o` files and the terminal are sinks too.
+pub trait ResultSink: Send + Sync {
+    fn write(&mut self, hit: &ScanHit) -> std::io::Result<()>;
+
+    // called after each hit, so results are on disk while the scan runs
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    // the title is only parsed out of the body when a sink shows it
+    fn wants_title(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    JsonLines,
+    Csv,
+}
+
+impl OutputFormat {
+    /// Guess the format from the file extension, plain text by default
+    pub fn from_path(path: &str) -> Self {
+        let ext = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+        match ext.as_str() {
+            "jsonl" | "json" | "ndjson" => OutputFormat::JsonLines,
+            "csv" => OutputFormat::Csv,
+            _ => OutputFormat::Text,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" | "txt" => Ok(OutputFormat::Text),
+            "jsonl" | "json" => Ok(OutputFormat::JsonLines),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unknown output format \"{s}\". Available formats: text, jsonl, csv")),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Text => "text",
+            OutputFormat::JsonLines => "jsonl",
+            OutputFormat::Csv => "csv",
+        })
+    }
+}
+
+/// Split the `-o` value into path and format, eg. 'hits.out:jsonl'
+pub fn split_format(value: &str) -> (String, OutputFormat) {
+    if let Some((path, format)) = value.rsplit_once(':') {
+        if let Ok(format) = OutputFormat::from_str(format) {
+            return (path.to_string(), format);
+        }
+    }
+    (value.to_string(), OutputFormat::from_path(value))
+}
+
+/// Open a file sink, an existing file is appended to.
+pub fn open_sink(path: &str, format: &OutputFormat) -> std::io::Result<Box<dyn ResultSink>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    // the csv header is only written to a new file
+    let is_empty = file.metadata()?.len() == 0;
+    let writer = BufWriter::new(file);
+    Ok(match format {
+        OutputFormat::Text => Box::new(TextSink::new(writer)),
+        OutputFormat::JsonLines => Box::new(JsonLinesSink::new(writer)),
+        OutputFormat::Csv => Box::new(CsvSink::new(writer, is_empty)),
+    })
+}
+
+/// One url per line
+pub struct TextSink<W: Write + Send + Sync> {
+    writer: W,
+}
+
+impl<W: Write + Send + Sync> TextSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+        }
+    }
+}
+
+impl<W: Write + Send + Sync> ResultSink for TextSink<W> {
+    fn write(&mut self, hit: &ScanHit) -> std::io::Result<()> {
+        writeln!(self.writer, "{}", hit.url)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub struct JsonLinesSink<W: Write + Send + Sync> {
+    writer: W,
+}
+
+impl<W: Write + Send + Sync> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+        }
+    }
+}
+
+/// One JSON Lines record, self-contained so it can be loaded without the rest of the file
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    payload: &'a str,
+    url: &'a str,
+    status: u16,
+    size: usize,
+    lines: usize,
+    words: usize,
+    // milliseconds
+    ttfb: u64,
+    duration: u64,
+    ip: Option<String>,
+    content_type: Option<&'a str>,
+    location: Option<&'a str>,
+    title: Option<&'a str>,
+    server: Option<&'a str>,
+    extracted: BTreeMap<&'a str, Vec<&'a str>>,
+    script_output: &'a str,
+}
+
+impl<'a> From<&'a ScanHit> for JsonRecord<'a> {
+    fn from(hit: &'a ScanHit) -> Self {
+        Self {
+            payload: &hit.payload,
+            url: &hit.url,
+            status: hit.status,
+            size: hit.size,
+            lines: hit.lines,
+            words: hit.words,
+            ttfb: hit.ttfb.as_millis() as u64,
+            duration: hit.duration.as_millis() as u64,
+            ip: hit.remote_addr.map(|addr| addr.ip().to_string()),
+            content_type: hit.content_type.as_deref(),
+            location: hit.location.as_deref(),
+            title: hit.title.as_deref(),
+            server: hit.server.as_deref(),
+            extracted: group(&hit.extracted),
+            script_output: &hit.script_output,
+        }
+    }
+}
+
+impl<W: Write + Send + Sync> ResultSink for JsonLinesSink<W> {
+    fn write(&mut self, hit: &ScanHit) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.writer, &JsonRecord::from(hit))?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn wants_title(&self) -> bool {
+        true
+    }
+}
+
+const CSV_HEADER: [&str; 15] = ["payload", "url", "status", "size", "lines", "words", "ttfb", "duration", "ip",
+    "content_type", "location", "title", "server", "extracted", "script_output"];
+
+pub struct CsvSink<W: Write + Send + Sync> {
+    writer: W,
+    header: bool,
+}
+
+impl<W: Write + Send + Sync> CsvSink<W> {
+    /// `header` writes the column names before the first hit
+    pub fn new(writer: W, header: bool) -> Self {
+        Self {
+            writer,
+            header,
+        }
+    }
+}
+
+// RFC 4180, quote fields holding a separator, a quote or a line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+    value.to_string()
+}
+
+impl<W: Write + Send + Sync> ResultSink for CsvSink<W> {
+    fn write(&mut self, hit: &ScanHit) -> std::io::Result<()> {
+        if self.header {
+            writeln!(self.writer, "{}", CSV_HEADER.join(","))?;
+            self.header = false;
+        }
+        let row = [
+            hit.payload.clone(),
+            hit.url.clone(),
+            hit.status.to_string(),
+            hit.size.to_string(),
+            hit.lines.to_string(),
+            hit.words.to_string(),
+            hit.ttfb.as_millis().to_string(),
+            hit.duration.as_millis().to_string(),
+            hit.remote_addr.map(|addr| addr.ip().to_string()).unwrap_or_default(),
+            hit.content_type.clone().unwrap_or_default(),
+            hit.location.clone().unwrap_or_default(),
+            hit.title.clone().unwrap_or_default(),
+            hit.server.clone().unwrap_or_default(),
+            format_values(&hit.extracted),
+            hit.script_output.clone(),
+        ];
+        let row: Vec<String> = row.iter().map(|v| csv_field(v)).collect();
+        writeln!(self.writer, "{}", row.join(","))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn wants_title(&self) -> bool {
+        true
+    }
+}
+
+/// Optional columns of the terminal lines, like httpx -title -server -location
+#[derive(Clone, Debug, PartialEq)]
+pub enum Column {
+    Title,
+    Server,
+    Location,
+}
+
+impl Column {
+    fn value<'a>(&self, hit: &'a ScanHit) -> Option<&'a str> {
+        match self {
+            Column::Title => hit.title.as_deref(),
+            Column::Server => hit.server.as_deref(),
+            Column::Location => hit.location.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Column::Title => "title",
+            Column::Server => "server",
+            Column::Location => "location",
+        })
+    }
+}
+
+/// Hits above the progress bar, the details are shown in debug mode.
+pub struct TerminalSink {
+    sender: Sender<String>,
+    mode: ScanMode,
+    columns: Vec<Column>,
+}
+
+impl TerminalSink {
+    pub fn new(sender: Sender<String>, mode: ScanMode) -> Self {
+        Self {
+            sender,
+            mode,
+            columns: vec![],
+        }
+    }
+
+    pub fn with_columns(mut self, columns: Vec<Column>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    // eg. " [Admin login] [nginx/1.25]", empty values are skipped
+    fn columns(columns: &[Column], hit: &ScanHit) -> String {
+        columns.iter()
+            .filter_map(|c| c.value(hit))
+            .map(|v| format!(" [{v}]"))
+            .collect()
+    }
+
+    pub fn detail(hit: &ScanHit, columns: &[Column]) -> String {
+        let ip = match hit.remote_addr {
+            Some(s) => s.ip().to_string(),
+            None => String::from("None"),
+        };
+        let mut output = format!("[Status: {}, Size: {}, Lines: {}, TTFB: {}ms, Duration: {}ms, IP: {}]\n\t* {}",
+                                 hit.status, hit.size, hit.lines, hit.ttfb.as_millis(), hit.duration.as_millis(), ip, hit.url);
+        output += &Self::columns(columns, hit);
+        if !hit.extracted.is_empty() {
+            output += &format!("\textract: {}", format_values(&hit.extracted))
+        }
+        if !hit.script_output.is_empty() {
+            output += &format!("\toutput: {}", hit.script_output)
+        }
+        output
+    }
+}
+
+impl ResultSink for TerminalSink {
+    fn write(&mut self, hit: &ScanHit) -> std::io::Result<()> {
+        let msg = if self.mode == ScanMode::Debug {
+            let color = match &hit.status {
+                200 => Style::new().green().bold(),
+                301 => Style::new().blue(),
+                404 => Style::new().dim().bold(),
+                403 => Style::new().yellow().bold(),
+                500 => Style::new().red().bold(),
+                400 => Style::new().dim().bold(),
+                401 => Style::new().blue().bold(),
+                _ => Style::new().cyan().bold(),
+            };
+            color.apply_to(Self::detail(hit, &self.columns)).to_string()
+        } else if !hit.extracted.is_empty() {
+            format!("{}{} [{}]", hit.url, Self::columns(&self.columns, hit), format_values(&hit.extracted))
+        } else {
+            format!("{}{}", hit.url, Self::columns(&self.columns, hit))
+        };
+        self.sender.send(msg)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::BrokenPipe, e))
+    }
+
+    fn wants_title(&self) -> bool {
+        self.columns.contains(&Column::Title)
+    }
+}
+
+// --extract-out values remembered for the dedup, around 100MB of short values
+pub const MAX_EXTRACT_SEEN: usize = 1_000_000;
+
+/// Open the --extract-out file, values already in it are not written again.
+pub fn open_extract_sink(path: &str) -> std::io::Result<ExtractSink<BufWriter<File>>> {
+    let mut seen = HashSet::new();
+    if let Ok(file) = File::open(path) {
+        for line in BufReader::new(file).lines().take(MAX_EXTRACT_SEEN) {
+            seen.insert(line?);
+        }
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    Ok(ExtractSink::new(BufWriter::new(file), seen, MAX_EXTRACT_SEEN))
+}
+
+/// Extracted values, one per line, each value is written once per file.
+/// Only the first `capacity` values are remembered, later ones may repeat.
+pub struct ExtractSink<W: Write + Send + Sync> {
+    writer: W,
+    seen: HashSet<String>,
+    capacity: usize,
+}
+
+impl<W: Write + Send + Sync> ExtractSink<W> {
+    pub fn new(writer: W, seen: HashSet<String>, capacity: usize) -> Self {
+        Self {
+            writer,
+            seen,
+            capacity,
+        }
+    }
+}
+
+impl<W: Write + Send + Sync> ResultSink for ExtractSink<W> {
+    fn write(&mut self, hit: &ScanHit) -> std::io::Result<()> {
+        for (_, value) in hit.extracted.iter() {
+            // one value per line
+            let value = value.replace(['\r', '\n'], " ");
+            if self.seen.contains(&value) {
+                continue;
+            }
+            writeln!(self.writer, "{value}")?;
+            if self.seen.len() < self.capacity {
+                self.seen.insert(value);
+                if self.seen.len() == self.capacity {
+                    log::warn!("[extract] {} values remembered, new ones may be written more than once", self.capacity);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -1,5 +1,7 @@
 #[cfg(test)]
 mod parameter_test {
+    use std::fs::{remove_file, write};
+    use kenshi::expr::ResponseView;
     use crate::parse_args;
 
     #[test]
@@ -27,4 +29,1028 @@ mod parameter_test {
         assert!(parse_args(&args).is_err());
         //dbg!(&parse_args(&args));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_header_regex_case() {
+        let test_file = "/tmp/kenshi_header_regex.txt";
+        write(test_file, "a\nb").unwrap();
+        let args: Vec<String> = ["program", "-u", "http://test.xxx/FUZZ", "-w", test_file, "--mh", "^Server: nginx"]
+            .iter().map(|a| a.to_string()).collect();
+        let params = parse_args(&args).unwrap();
+        let headers = vec![("server".to_string(), "nginx/1.25".to_string())];
+        let view = ResponseView::new(200, "/", "").with_headers(&headers);
+        assert!(params.custom_matches.unwrap().eval(&view));
+        let _ = remove_file(test_file);
+    }
+
+    #[test]
+    fn test_data_method() {
+        let test_file = "/tmp/kenshi_data_method.txt";
+        write(test_file, "a\nb").unwrap();
+        let args = |extra: &[&str]| -> Vec<String> {
+            let mut args = vec!["program", "-u", "http://test.xxx/login", "-w", test_file, "-d", "user=FUZZ"];
+            args.extend_from_slice(extra);
+            args.iter().map(|a| a.to_string()).collect()
+        };
+        let params = parse_args(&args(&[])).unwrap();
+        assert_eq!(params.request.method, "POST");
+        assert_eq!(params.request.body, Some(b"user=FUZZ".to_vec()));
+        let params = parse_args(&args(&["-X", "PUT"])).unwrap();
+        assert_eq!(params.request.method, "PUT");
+        let _ = remove_file(test_file);
+    }
+}
+#[cfg(test)]
+mod wordlist_test {
+    use std::fs::{remove_file, write};
+    use kenshi::wordlist::{count_lines, DEDUP_FALSE_POSITIVE, FuzzMode, split_keyword, Wordlist, WordlistSet};
+
+    #[test]
+    fn test_dedup() {
+        let words = "/tmp/kenshi_wordlist_dedup.txt";
+        let ids = "/tmp/kenshi_wordlist_dedup_ids.txt";
+        write(words, "admin\nlogin\nadmin\nlogin\nbackup").unwrap();
+        write(ids, "1\n2\n1").unwrap();
+        let lists = vec![
+            Wordlist::from_file(ids, "ID", None).unwrap(),
+            Wordlist::from_file(words, "FUZZ", None).unwrap(),
+        ];
+        // off by default
+        let set = WordlistSet::new(lists.clone(), FuzzMode::Clusterbomb);
+        assert_eq!(set.len(), 15);
+        assert_eq!(set.payloads().unwrap().count(), 15);
+
+        // the second list is rewound for each id, its duplicates are skipped every time.
+        // they are found while reading, len() still counts them
+        let set = WordlistSet::new(lists, FuzzMode::Clusterbomb).with_dedup();
+        assert_eq!(set.len(), 15);
+        let payloads: Vec<String> = set.payloads().unwrap().map(|p| p.fill("ID/FUZZ")).collect();
+        assert_eq!(payloads, vec!["1/admin", "1/login", "1/backup", "2/admin", "2/login", "2/backup"]);
+        let _ = remove_file(words);
+        let _ = remove_file(ids);
+    }
+
+    #[test]
+    fn test_pitchfork_dedup() {
+        let users = "/tmp/kenshi_wordlist_pitchfork_users.txt";
+        let passwords = "/tmp/kenshi_wordlist_pitchfork_passwords.txt";
+        write(users, "admin\nadmin\nroot\nadmin").unwrap();
+        write(passwords, "admin\nsecret\ntoor\nadmin").unwrap();
+        let lists = vec![
+            Wordlist::from_file(users, "USER", None).unwrap(),
+            Wordlist::from_file(passwords, "PASS", None).unwrap(),
+        ];
+        // a repeated user doesn't shift the pairs, only the repeated pair goes
+        let set = WordlistSet::new(lists, FuzzMode::Pitchfork).with_dedup();
+        let payloads: Vec<String> = set.payloads().unwrap().map(|p| p.fill("USER:PASS")).collect();
+        assert_eq!(payloads, vec!["admin:admin", "admin:secret", "root:toor"]);
+        let _ = remove_file(users);
+        let _ = remove_file(passwords);
+    }
+
+    #[test]
+    fn test_lazy_payloads() {
+        let test_file = "/tmp/kenshi_wordlist_test.txt";
+        write(test_file, "a\nb\na\nindex.%EXT%").unwrap();
+        assert_eq!(count_lines(test_file, None).unwrap(), 4);
+        assert_eq!(count_lines(test_file, Some(2)).unwrap(), 5);
+
+        let wordlist = Wordlist::from_file(test_file, "FUZZ", Some(vec!["php".to_string(), "bak".to_string()])).unwrap();
+        let payloads: Vec<String> = wordlist.payloads().unwrap().collect();
+        assert_eq!(payloads, vec!["a", "b", "a", "index.php", "index.bak"]);
+        let wordlist = wordlist.with_dedup();
+        let payloads: Vec<String> = wordlist.payloads().unwrap().collect();
+        assert_eq!(payloads, vec!["a", "b", "index.php", "index.bak"]);
+        let _ = remove_file(test_file);
+    }
+
+    #[test]
+    fn test_dedup_false_positives() {
+        let test_file = "/tmp/kenshi_wordlist_bloom.txt";
+        let lines: Vec<String> = (0..200_000).map(|i| format!("word{i}")).collect();
+        write(test_file, lines.join("\n")).unwrap();
+        // every line is new, the few taken for repeats stay near the stated odds
+        let wordlist = Wordlist::from_file(test_file, "FUZZ", None).unwrap().with_dedup();
+        let kept = wordlist.payloads().unwrap().count();
+        assert!(lines.len() - kept <= (lines.len() as f64 * DEDUP_FALSE_POSITIVE * 5.0) as usize);
+        // the same lines are skipped on every read, a resumed scan lines up
+        assert_eq!(wordlist.payloads().unwrap().count(), kept);
+        let _ = remove_file(test_file);
+    }
+
+    #[test]
+    fn test_wordlist_modes() {
+        let users = "/tmp/kenshi_wordlist_users.txt";
+        let pass = "/tmp/kenshi_wordlist_pass.txt";
+        write(users, "root\nadmin\n").unwrap();
+        write(pass, "123\nabc\nqwe\n").unwrap();
+        assert_eq!(split_keyword("/tmp/a.txt:USER"), ("/tmp/a.txt".to_string(), "USER".to_string()));
+        assert_eq!(split_keyword("/tmp/a.txt"), ("/tmp/a.txt".to_string(), "FUZZ".to_string()));
+
+        let lists = vec![
+            Wordlist::from_file(users, "USER", None).unwrap(),
+            Wordlist::from_file(pass, "PASS", None).unwrap(),
+        ];
+        let template = "/login?u=USER&p=PASS";
+        let fill = |mode: FuzzMode| -> (usize, Vec<String>) {
+            let set = WordlistSet::new(lists.clone(), mode);
+            (set.len(), set.payloads().unwrap().map(|p| p.fill(template)).collect())
+        };
+
+        let (len, urls) = fill(FuzzMode::Clusterbomb);
+        assert_eq!(len, 6);
+        assert_eq!(urls, vec!["/login?u=root&p=123", "/login?u=root&p=abc", "/login?u=root&p=qwe",
+                              "/login?u=admin&p=123", "/login?u=admin&p=abc", "/login?u=admin&p=qwe"]);
+        let (len, urls) = fill(FuzzMode::Pitchfork);
+        assert_eq!(len, 2);
+        assert_eq!(urls, vec!["/login?u=root&p=123", "/login?u=admin&p=abc"]);
+        let (len, urls) = fill(FuzzMode::Sniper);
+        assert_eq!(len, 5);
+        assert_eq!(urls, vec!["/login?u=root&p=", "/login?u=admin&p=",
+                              "/login?u=&p=123", "/login?u=&p=abc", "/login?u=&p=qwe"]);
+        let _ = remove_file(users);
+        let _ = remove_file(pass);
+    }
+}
+
+#[cfg(test)]
+mod rawhttp_test {
+    use kenshi::rawhttp::RequestTemplate;
+    use kenshi::wordlist::Payload;
+
+    #[test]
+    fn test_raw_request_template() {
+        let raw = "POST /api/FUZZ HTTP/1.1\r\nHost: example.com\r\nX-Token: FUZZ\r\nContent-Length: 9\r\n\r\nname=FUZZ";
+        let template = RequestTemplate::from_raw(raw, "https", None).unwrap();
+        assert_eq!(template.method, "POST");
+        assert_eq!(template.url, "https://example.com/api/FUZZ");
+        assert_eq!(template.headers, vec![("X-Token".to_string(), "FUZZ".to_string())]);
+        assert!(template.contains("FUZZ"));
+
+        let payload = Payload { words: vec![("FUZZ".to_string(), "admin".to_string())] };
+        let request = template.fill(&payload);
+        assert_eq!(request.url, "https://example.com/api/admin");
+        assert_eq!(request.headers[0].1, "admin");
+        assert_eq!(request.body, Some(b"name=admin".to_vec()));
+
+        assert!(RequestTemplate::from_raw("GET /FUZZ HTTP/1.1\r\n\r\n", "https", None).is_err());
+        assert!(RequestTemplate::from_raw("GET /FUZZ HTTP/1.1\r\nHost example.com\r\n\r\n", "https", None).is_err());
+    }
+
+    #[test]
+    fn test_raw_request_host() {
+        let client = reqwest::Client::new();
+        let host = |template: &RequestTemplate| {
+            let request = template.build(&client).unwrap();
+            request.headers().get("host").map(|v| v.to_str().unwrap().to_string())
+        };
+        let payload = Payload { words: vec![("FUZZ".to_string(), "admin".to_string())] };
+
+        // absolute-form, the Host header is fuzzed apart from the url
+        let raw = "GET http://10.0.0.1/ HTTP/1.1\r\nHost: FUZZ.example.com\r\n\r\n";
+        let template = RequestTemplate::from_raw(raw, "https", None).unwrap();
+        assert_eq!(template.url, "http://10.0.0.1/");
+        assert!(template.contains("FUZZ"));
+        assert_eq!(host(&template.fill(&payload)), Some("admin.example.com".to_string()));
+
+        // -u replaces the url, Host stays
+        let raw = "GET /login HTTP/1.1\r\nHost: FUZZ.example.com\r\n\r\n";
+        let template = RequestTemplate::from_raw(raw, "https", Some("http://10.0.0.1/login")).unwrap();
+        assert_eq!(template.url, "http://10.0.0.1/login");
+        assert_eq!(host(&template.fill(&payload)), Some("admin.example.com".to_string()));
+
+        // otherwise the url is made of it and the client sends it
+        let template = RequestTemplate::from_raw(raw, "https", None).unwrap();
+        assert_eq!(template.url, "https://FUZZ.example.com/login");
+        assert!(template.headers.is_empty());
+        assert_eq!(host(&template), None);
+    }
+
+    #[test]
+    fn test_raw_request_body_bytes() {
+        // multipart bodies need their CRLF, blank lines inside the body stay too
+        let body = "--b\r\nContent-Disposition: form-data; name=\"f\"\r\n\r\nFUZZ\r\n--b--\r\n";
+        let raw = format!("POST /upload HTTP/1.1\r\nHost: example.com\r\nContent-Type: multipart/form-data; boundary=b\r\n\r\n{body}");
+        let template = RequestTemplate::from_raw(&raw, "https", None).unwrap();
+        assert_eq!(template.headers.len(), 1);
+        assert_eq!(template.body.as_deref(), Some(body.as_bytes()));
+
+        // LF only heads are still accepted
+        let template = RequestTemplate::from_raw("POST / HTTP/1.1\nHost: example.com\n\na\r\nb", "http", None).unwrap();
+        assert_eq!(template.url, "http://example.com/");
+        assert_eq!(template.body.as_deref(), Some(&b"a\r\nb"[..]));
+
+        // a binary upload is read as is
+        let path = "/tmp/kenshi_raw_request_binary.txt";
+        let mut raw = b"POST /upload HTTP/1.1\r\nHost: example.com\r\n\r\n".to_vec();
+        raw.extend_from_slice(b"\x89PNG\r\n\x1a\n\xff\xfeFUZZ\x00");
+        std::fs::write(path, &raw).unwrap();
+        let template = RequestTemplate::from_file(path, "https", None).unwrap();
+        assert!(template.contains("FUZZ"));
+        let payload = Payload { words: vec![("FUZZ".to_string(), "admin".to_string())] };
+        assert_eq!(template.fill(&payload).body.unwrap(), b"\x89PNG\r\n\x1a\n\xff\xfeadmin\x00".to_vec());
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod request_option_test {
+    use kenshi::rawhttp::{parse_header, RequestTemplate};
+
+    #[test]
+    fn test_header_option() {
+        assert_eq!(parse_header("X-Api-Key: FUZZ").unwrap(), ("X-Api-Key".to_string(), "FUZZ".to_string()));
+        assert!(parse_header("X-Api-Key FUZZ").is_err());
+        assert!(parse_header(": FUZZ").is_err());
+
+        let mut template = RequestTemplate::from_url("https://example.com/FUZZ");
+        template.set_header("User-Agent", "kenshi");
+        template.set_header("user-agent", "FUZZ");
+        assert_eq!(template.headers, vec![("user-agent".to_string(), "FUZZ".to_string())]);
+        assert!(template.validate().is_ok());
+
+        template.set_header("X-Bad Name", "1");
+        assert!(template.validate().is_err());
+        let mut template = RequestTemplate::from_url("https://example.com/FUZZ");
+        template.set_header("X-Token", "a\nb");
+        assert!(template.validate().is_err());
+        template.method = "GE T".to_string();
+        assert!(template.validate().is_err());
+    }
+}
+
+#[cfg(test)]
+mod checkpoint_test {
+    use std::fs::{create_dir_all, remove_file, write};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use kenshi::{Params, ScanEvent, Scanner, Stats};
+    use kenshi::checkpoint::{Checkpoint, CHECKPOINT_DIR, Progress};
+    use kenshi::data_type::ScanStats;
+
+    #[test]
+    fn test_progress_watermark() {
+        let mut progress = Progress::new(10);
+        progress.complete(12);
+        progress.complete(11);
+        assert_eq!(progress.position, 10);
+        progress.complete(10);
+        assert_eq!(progress.position, 13);
+        progress.complete(14);
+        assert_eq!(progress.position, 13);
+    }
+
+    #[test]
+    fn test_checkpoint_remove() {
+        create_dir_all(CHECKPOINT_DIR).unwrap();
+        let checkpoint = Checkpoint::new(&Checkpoint::new_id(), &[]);
+        let (path, hits_path) = (Checkpoint::path(&checkpoint.id), Checkpoint::hits_path(&checkpoint.id));
+        write(&path, "").unwrap();
+        write(&hits_path, "").unwrap();
+        checkpoint.remove();
+        assert!(!Path::new(&path).exists());
+        assert!(!Path::new(&hits_path).exists());
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        create_dir_all(CHECKPOINT_DIR).unwrap();
+        let mut checkpoint = Checkpoint::new(&Checkpoint::new_id(), &["kenshi".to_string()]);
+        for index in [0, 1, 3, 4] {
+            checkpoint.progress.complete(index);
+        }
+        let stats = ScanStats::new();
+        stats.set(&Stats::Done, 4);
+        checkpoint.save(&stats).unwrap();
+
+        let loaded = Checkpoint::load(&checkpoint.id).unwrap();
+        assert_eq!(loaded.args, checkpoint.args);
+        assert_eq!(loaded.progress.position, 2);
+        assert!(loaded.progress.is_done(4) && !loaded.progress.is_done(2));
+        let stats = ScanStats::new();
+        loaded.restore_stats(&stats);
+        assert_eq!(stats.get(&Stats::Done), 4);
+        loaded.remove();
+    }
+
+    // answers 200 a bit late, counting the requests
+    async fn serve(served: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let served = served.clone();
+                tokio::spawn(async move {
+                    let _ = stream.read(&mut [0u8; 1024]).await;
+                    served.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+                });
+            }
+        });
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_resume() {
+        create_dir_all(CHECKPOINT_DIR).unwrap();
+        let served = Arc::new(AtomicUsize::new(0));
+        let url = format!("http://{}/FUZZ", serve(served.clone()).await);
+        let words = "/tmp/kenshi_checkpoint_resume.txt";
+        let lines: Vec<String> = (0..40).map(|i| format!("w{i}")).collect();
+        write(words, lines.join("\n")).unwrap();
+        let params = |checkpoint: Checkpoint, resumed: bool| {
+            let builder = Params::builder().url(&url).wordlist(words).concurrency(4);
+            match resumed {
+                true => builder.resume(checkpoint),
+                false => builder.checkpoint(checkpoint),
+            }.build()
+        };
+        let scan = |params: Params| async move {
+            let scanner = Scanner::new(params);
+            let (stats, stop) = (scanner.stats(), scanner.stop_handle());
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                stop.store(false, std::sync::atomic::Ordering::SeqCst);
+            });
+            let mut interrupted = false;
+            let mut events = scanner.run();
+            while let Some(event) = events.next().await {
+                if let ScanEvent::Finished(summary) = event {
+                    interrupted = summary.interrupted;
+                }
+            }
+            (stats, interrupted)
+        };
+
+        let id = Checkpoint::new_id();
+        let (_, interrupted) = scan(params(Checkpoint::new(&id, &[]), false).unwrap()).await;
+        assert!(interrupted);
+        let checkpoint = Checkpoint::load(&id).unwrap();
+        assert!(checkpoint.progress.count() > 0 && checkpoint.progress.count() < 40);
+
+        // another wordlist would shift the payloads
+        write(words, lines[..39].join("\n")).unwrap();
+        assert!(params(checkpoint.clone(), true).is_err());
+        write(words, lines.join("\n")).unwrap();
+
+        // the scan goes on where it stopped, until it's done
+        let mut checkpoint = checkpoint;
+        let stats = loop {
+            let (stats, interrupted) = scan(params(checkpoint, true).unwrap()).await;
+            if !interrupted {
+                break stats;
+            }
+            checkpoint = Checkpoint::load(&id).unwrap();
+        };
+        // every payload sent once, counted once
+        assert_eq!(served.load(Ordering::SeqCst), 40);
+        assert_eq!(stats.get(&Stats::Done), 40);
+        assert_eq!(stats.get(&Stats::C200), 40);
+        assert!(!Path::new(&Checkpoint::path(&id)).exists());
+        let _ = remove_file(words);
+    }
+}
+
+#[cfg(test)]
+mod sink_test {
+    use std::time::Duration;
+    use kenshi::data_type::ScanHit;
+    use kenshi::sink::{CsvSink, ExtractSink, JsonLinesSink, OutputFormat, ResultSink, split_format, TextSink};
+
+    fn hit() -> ScanHit {
+        ScanHit {
+            payload: "admin".to_string(),
+            url: "https://example.com/admin".to_string(),
+            status: 200,
+            size: 12,
+            lines: 1,
+            words: 2,
+            ttfb: Duration::from_millis(30),
+            duration: Duration::from_millis(35),
+            remote_addr: None,
+            content_type: Some("text/html".to_string()),
+            location: None,
+            title: Some("Admin, login".to_string()),
+            server: None,
+            extracted: vec![("email".to_string(), "a@example.com".to_string()),
+                            ("email".to_string(), "b@example.com".to_string())],
+            script_output: "a,\"b\"".to_string(),
+            body: vec![],
+        }
+    }
+
+    #[test]
+    fn test_output_format() {
+        assert_eq!(split_format("hits.jsonl"), ("hits.jsonl".to_string(), OutputFormat::JsonLines));
+        assert_eq!(split_format("hits.CSV"), ("hits.CSV".to_string(), OutputFormat::Csv));
+        assert_eq!(split_format("hits.out:csv"), ("hits.out".to_string(), OutputFormat::Csv));
+        assert_eq!(split_format("hits"), ("hits".to_string(), OutputFormat::Text));
+        assert_eq!(split_format("c:/hits.json"), ("c:/hits.json".to_string(), OutputFormat::JsonLines));
+    }
+
+    #[test]
+    fn test_sinks() {
+        let mut csv = vec![];
+        {
+            let mut sink = CsvSink::new(&mut csv, true);
+            sink.write(&hit()).unwrap();
+            sink.write(&hit()).unwrap();
+        }
+        let csv = String::from_utf8(csv).unwrap();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[0].starts_with("payload,url,status"));
+        assert_eq!(rows[1], r#"admin,https://example.com/admin,200,12,1,2,30,35,,text/html,,"Admin, login",,"email: a@example.com, b@example.com","a,""b""""#);
+
+        let mut jsonl = vec![];
+        JsonLinesSink::new(&mut jsonl).write(&hit()).unwrap();
+        let record: serde_json::Value = serde_json::from_slice(&jsonl).unwrap();
+        assert_eq!(record["status"], 200);
+        assert_eq!(record["duration"], 35);
+        assert_eq!(record["ttfb"], 30);
+        assert_eq!(record["words"], 2);
+        assert_eq!(record["content_type"], "text/html");
+        assert!(record["location"].is_null());
+        assert_eq!(record["script_output"], "a,\"b\"");
+        assert_eq!(record["extracted"]["email"][1], "b@example.com");
+        assert_eq!(record["title"], "Admin, login");
+        assert!(record["server"].is_null());
+
+        // the title is only parsed for the sinks showing it
+        assert!(JsonLinesSink::new(vec![]).wants_title());
+        assert!(CsvSink::new(vec![], true).wants_title());
+        assert!(!TextSink::new(vec![]).wants_title());
+    }
+
+    #[test]
+    fn test_extract_sink() {
+        let mut out = vec![];
+        {
+            let seen = ["b@example.com".to_string()].into_iter().collect();
+            let mut sink = ExtractSink::new(&mut out, seen, 10);
+            sink.write(&hit()).unwrap();
+            sink.write(&hit()).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "a@example.com\n");
+
+        // nothing more is remembered once full, the values are still written
+        let mut out = vec![];
+        {
+            let seen = ["b@example.com".to_string()].into_iter().collect();
+            let mut sink = ExtractSink::new(&mut out, seen, 1);
+            sink.write(&hit()).unwrap();
+            sink.write(&hit()).unwrap();
+        }
+        assert_eq!(String::from_utf8(out).unwrap(), "a@example.com\na@example.com\n");
+    }
+}
+
+#[cfg(test)]
+mod extract_test {
+    use regex::Regex;
+    use kenshi::extract::{extract, format_values};
+
+    #[test]
+    fn test_extract() {
+        let body = r#"<a href="mailto:admin@example.com">admin@example.com</a> key: AKIA1234, key: AKIA5678"#;
+        let regexes = vec![
+            Regex::new(r"[\w.]+@[\w.]+\w").unwrap(),
+            Regex::new(r"key: (?P<key>AKIA\d+)").unwrap(),
+        ];
+        let values = extract(&regexes, body);
+        assert_eq!(values, vec![
+            ("match".to_string(), "admin@example.com".to_string()),
+            ("key".to_string(), "AKIA1234".to_string()),
+            ("key".to_string(), "AKIA5678".to_string()),
+        ]);
+        assert_eq!(format_values(&values), "key: AKIA1234, AKIA5678; match: admin@example.com");
+        assert!(extract(&regexes, "nothing").is_empty());
+    }
+}
+
+#[cfg(test)]
+mod calibration_test {
+    use kenshi::calibration::fingerprints;
+    use kenshi::data_type::Fingerprint;
+
+    #[test]
+    fn test_calibration_fingerprints() {
+        let probes = vec![
+            // the random path is echoed back, only the word count is stable
+            Fingerprint::of(200, b"<p>Page /abcd was not found</p>"),
+            Fingerprint::of(200, b"<p>Page /abcdefgh was not found</p>"),
+            Fingerprint::of(403, b"forbidden"),
+            Fingerprint::of(403, b"forbidden"),
+        ];
+        let filters = fingerprints(&probes);
+        assert_eq!(filters, vec![
+            Fingerprint { status: 200, size: None, lines: None, words: Some(5) },
+            Fingerprint { status: 403, size: Some(9), lines: None, words: None },
+        ]);
+        assert!(filters[0].matches(&Fingerprint::of(200, b"<p>Page /admin.php was not found</p>")));
+        assert!(!filters[0].matches(&Fingerprint::of(200, b"<p>Welcome admin</p>")));
+        assert!(!filters[1].matches(&Fingerprint::of(401, b"forbidden")));
+    }
+}
+
+#[cfg(test)]
+mod lru_test {
+    use kenshi::lru::{host_of, LruCache};
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!(host_of("https://a.com/x?y=1"), Some("https://a.com".to_string()));
+        assert_eq!(host_of("http://a.com:8080/"), Some("http://a.com:8080".to_string()));
+        // the default port is the same host
+        assert_eq!(host_of("https://a.com:443/x"), host_of("https://a.com/y"));
+        assert_eq!(host_of("/relative"), None);
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut cache = LruCache::new(2);
+        cache.insert("https://a.com", 1);
+        cache.insert("https://b.com", 2);
+        // a.com is used again, b.com becomes the oldest
+        assert_eq!(cache.get("https://a.com"), Some(1));
+        cache.insert("https://c.com", 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("https://b.com"), None);
+        assert_eq!(cache.get("https://a.com"), Some(1));
+        cache.insert("https://a.com", 4);
+        assert_eq!(cache.get("https://a.com"), Some(4));
+        assert_eq!(cache.get("https://c.com"), Some(3));
+    }
+}
+
+#[cfg(test)]
+mod expr_test {
+    use kenshi::expr::{Expr, NumRule, quote, ResponseView};
+
+    #[test]
+    fn test_expr_eval() {
+        let expr = Expr::parse(r#"status in [200,403] && size > 10 && !body ~ "Not Found""#).unwrap();
+        assert!(expr.eval(&ResponseView::new(200, "/admin", "<h1>Welcome admin</h1>")));
+        assert!(!expr.eval(&ResponseView::new(200, "/x", "<h1>Not Found</h1>")));
+        assert!(!expr.eval(&ResponseView::new(404, "/admin", "<h1>Welcome admin</h1>")));
+        assert!(!expr.eval(&ResponseView::new(403, "/admin", "short")));
+
+        let expr = Expr::parse(r#"(status == 301 || status in [500-599]) && lines <= 2 && url != "/""#).unwrap();
+        assert!(expr.eval(&ResponseView::new(502, "/api", "a\nb")));
+        assert!(!expr.eval(&ResponseView::new(502, "/", "a\nb")));
+        assert!(!expr.eval(&ResponseView::new(502, "/api", "a\nb\nc")));
+        assert!(!expr.eval(&ResponseView::new(404, "/api", "a")));
+
+        let expr = Expr::parse(&format!("body ~ {} && words == 2", quote(r#"id="\d+""#))).unwrap();
+        assert!(expr.eval(&ResponseView::new(200, "/", r#"<p id="42">x</p>"#)));
+        assert!(!expr.eval(&ResponseView::new(200, "/", r#"<p id="ab">x</p>"#)));
+    }
+
+    #[test]
+    fn test_expr_headers() {
+        let headers = vec![
+            ("server".to_string(), "nginx/1.25".to_string()),
+            ("x-cache".to_string(), "HIT".to_string()),
+        ];
+        let view = ResponseView::new(200, "/", "")
+            .with_headers(&headers)
+            .with_content_type("application/json; charset=utf-8");
+        assert!(Expr::parse(r#"headers ~ "(?m)^x-cache: HIT$""#).unwrap().eval(&view));
+        assert!(Expr::parse(r#"headers !~ "(?i)apache""#).unwrap().eval(&view));
+        let expr = format!("content_type ~ {}", quote(r"(?i)^(application/json)\s*(;|$)"));
+        assert!(Expr::parse(&expr).unwrap().eval(&view));
+        assert!(!Expr::parse(r#"content_type == "text/html""#).unwrap().eval(&view));
+    }
+
+    #[test]
+    fn test_expr_timing() {
+        let view = ResponseView::new(200, "/", "").with_timing(5200, 5350);
+        assert!(Expr::parse("ttfb > 5000").unwrap().eval(&view));
+        assert!(Expr::parse("time in [5000-6000]").unwrap().eval(&view));
+        assert!(!Expr::parse("ttfb < 100 || time in [0-1000]").unwrap().eval(&view));
+        assert!(Expr::parse("ttfb > 5000").unwrap().eval_status(200).is_none());
+    }
+
+    #[test]
+    fn test_expr_status_only() {
+        let expr = Expr::parse(r#"status in [200,403] && body ~ "admin""#).unwrap();
+        assert_eq!(expr.eval_status(404), Some(false));
+        assert_eq!(expr.eval_status(200), None);
+        let expr = Expr::parse(r#"status == 404 || size < 10"#).unwrap();
+        assert_eq!(expr.eval_status(404), Some(true));
+        assert_eq!(expr.eval_status(200), None);
+        assert_eq!(Expr::parse("!(status >= 400)").unwrap().eval_status(500), Some(false));
+    }
+
+    #[test]
+    fn test_num_rules() {
+        let rules = NumRule::parse_list("<100, >=1000,10-50,60..70,1234").unwrap();
+        assert_eq!(rules, vec![NumRule::Lt(100), NumRule::Ge(1000), NumRule::Range(10, 50),
+                               NumRule::RangeExclusive(60, 70), NumRule::Eq(1234)]);
+        assert!(NumRule::Range(10, 50).matches(50));
+        assert!(!NumRule::RangeExclusive(60, 70).matches(70));
+        let source = rules.iter().map(|r| r.to_string()).collect::<Vec<String>>().join(",");
+        assert_eq!(source, "<100,>=1000,10-50,60..70,1234");
+        let expr = Expr::parse("size in [60..70, >=1000]").unwrap();
+        assert!(expr.eval(&ResponseView::new(200, "/", &"a".repeat(69))));
+        assert!(!expr.eval(&ResponseView::new(200, "/", &"a".repeat(70))));
+        for rule in ["", "abc", "10-", "-5", "50-10", "10..10", "<", ">>1", "1.5", "10-20-30"] {
+            assert!(rule.parse::<NumRule>().is_err(), "{rule}");
+        }
+        assert!(NumRule::parse_list("10,").is_err());
+    }
+
+    #[test]
+    fn test_expr_errors() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("status").is_err());
+        assert!(Expr::parse("status ~ \"200\"").is_err());
+        assert!(Expr::parse("body > 10").is_err());
+        assert!(Expr::parse("code == 200").is_err());
+        assert!(Expr::parse("status in [200,").is_err());
+        assert!(Expr::parse("status in [500-400]").is_err());
+        assert!(Expr::parse("body ~ \"(\"").is_err());
+        assert!(Expr::parse("(status == 200").is_err());
+        assert!(Expr::parse("status == 200 size == 1").is_err());
+    }
+}
+
+#[cfg(test)]
+mod status_rules_test {
+    use crate::params_parse::status_rules;
+
+    #[test]
+    fn test_status_rules() {
+        assert_eq!(status_rules("mc", "all").unwrap(), None);
+        assert_eq!(status_rules("mc", "200, 403").unwrap().unwrap(), "status in [200,403]");
+        assert_eq!(status_rules("mc", "2xx,400-499").unwrap().unwrap(), "status in [200-299,400-499]");
+        assert_eq!(status_rules("mc", "all,!404").unwrap().unwrap(), "!(status in [404])");
+        assert_eq!(status_rules("fc", "3XX,!304").unwrap().unwrap(), "(status in [300-399]) && (!(status in [304]))");
+    }
+
+    #[test]
+    fn test_status_rules_errors() {
+        for value in ["al", "200,abc", "", "200,", "6xx0", "0xx", "499-400", "!all", "70000", "99"] {
+            assert!(status_rules("mc", value).is_err(), "{value}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod cluster_test {
+    use kenshi::cluster::{CLUSTER_DISTANCE, Clusters, MAX_CLUSTERS, Membership, simhash};
+
+    fn page(path: &str, time: u64) -> String {
+        format!("<html><head><title>Page not found</title></head><body><div class=\"error\">\
+                 <h1>Oops, nothing here</h1><p>The page {path} you requested could not be found on this server. \
+                 It may have been moved, renamed or deleted. Please check the address or go back to the home page \
+                 and use the search box to find what you are looking for.</p><p>Request id {time}</p>\
+                 <footer>Copyright example company, all rights reserved</footer></div></body></html>")
+    }
+
+    #[test]
+    fn test_simhash() {
+        let a = simhash(page("/admin", 1700000000).as_bytes());
+        let b = simhash(page("/backup", 1700000123).as_bytes());
+        assert!((a ^ b).count_ones() <= CLUSTER_DISTANCE);
+        let other = simhash(b"<html><body><h1>Dashboard</h1><ul><li>users</li><li>settings</li></ul></body></html>");
+        assert!((a ^ other).count_ones() > CLUSTER_DISTANCE);
+        assert_eq!(simhash(b""), 0);
+    }
+
+    #[test]
+    fn test_clusters() {
+        let mut clusters = Clusters::new(2, MAX_CLUSTERS);
+        let hash = simhash(page("/a", 1).as_bytes());
+        assert_eq!(clusters.add(200, hash, "/a"), Membership::Keep);
+        assert_eq!(clusters.add(200, hash ^ 1, "/b"), Membership::Keep);
+        // same page, other status
+        assert_eq!(clusters.add(404, hash, "/c"), Membership::Keep);
+        assert_eq!(clusters.add(200, hash, "/d"),
+                   Membership::Overflow { representative: "/a".to_string(), members: 3 });
+        assert_eq!(clusters.add(200, hash, "/e"), Membership::Drop);
+        assert_eq!(clusters.add(200, !hash, "/f"), Membership::Keep);
+        assert_eq!(clusters.suppressed(), vec![("/a".to_string(), 4)]);
+
+        // past the cap new pages are kept as they are, the existing clusters still fill up
+        let mut clusters = Clusters::new(1, 1);
+        assert_eq!(clusters.add(200, hash, "/a"), Membership::Keep);
+        assert_eq!(clusters.add(200, !hash, "/b"), Membership::Keep);
+        assert_eq!(clusters.add(200, !hash, "/c"), Membership::Keep);
+        assert_eq!(clusters.add(200, hash, "/d"),
+                   Membership::Overflow { representative: "/a".to_string(), members: 2 });
+    }
+}
+
+#[cfg(test)]
+mod html_test {
+    use kenshi::html::{charset, decode_entities, title};
+
+    #[test]
+    fn test_title() {
+        let page = b"<html><head>\n<TITLE lang=en>\n  Tom &amp; Jerry&#39;s &#x2014; home\n</TITLE></head></html>";
+        assert_eq!(title(page, None).unwrap(), "Tom & Jerry's \u{2014} home");
+        assert_eq!(title(b"<html><title> </title></html>", None), None);
+        assert_eq!(title(b"no title", None), None);
+        assert_eq!(decode_entities("a & b &unknown; &#xZZ; &lt;"), "a & b &unknown; &#xZZ; <");
+    }
+
+    #[test]
+    fn test_title_charset() {
+        // "Привет" in windows-1251
+        let body = [b"<meta charset=\"windows-1251\"><title>".to_vec(), vec![0xcf, 0xf0, 0xe8, 0xe2, 0xe5, 0xf2], b"</title>".to_vec()].concat();
+        assert_eq!(charset(&body, None).name(), "windows-1251");
+        assert_eq!(title(&body, None).unwrap(), "Привет");
+        // the header wins over the meta tag
+        assert_eq!(charset(&body, Some("text/html; Charset=\"ISO-8859-1\"")).name(), "windows-1252");
+        assert_eq!(charset(b"<title>x</title>", Some("text/html")).name(), "UTF-8");
+    }
+}
+
+#[cfg(test)]
+mod ratelimit_test {
+    use std::time::{Duration, Instant};
+    use kenshi::ratelimit::{HostRateLimiter, parse_rate, RateLimiter};
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("50").unwrap(), 50.0);
+        assert_eq!(parse_rate("50/s").unwrap(), 50.0);
+        assert_eq!(parse_rate("600/m").unwrap(), 10.0);
+        assert_eq!(parse_rate("0.5/s").unwrap(), 0.5);
+        for rate in ["", "0", "-1/s", "abc", "10/d", "10/", "inf"] {
+            assert!(parse_rate(rate).is_err(), "{rate}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let limiter = RateLimiter::new(100.0);
+        let start = Instant::now();
+        for _ in 0..11 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        // hosts don't share their slots
+        let limiter = HostRateLimiter::new(2.0, 10);
+        let start = Instant::now();
+        limiter.acquire("http://a.example.com/x").await;
+        limiter.acquire("http://b.example.com/x").await;
+        limiter.acquire("http://a.example.com:8080/x").await;
+        assert!(start.elapsed() < Duration::from_millis(200));
+        limiter.acquire("http://a.example.com/y").await;
+        assert!(start.elapsed() >= Duration::from_millis(450));
+    }
+}
+
+#[cfg(test)]
+mod concurrency_test {
+    use std::sync::Arc;
+    use kenshi::concurrency::{AdaptiveConcurrency, is_overload};
+    use kenshi::data_type::Stats;
+
+    #[test]
+    fn test_overload() {
+        assert!(is_overload(&Stats::TimeOut));
+        assert!(is_overload(&Stats::ConnReset));
+        // the target answered, it isn't overloaded
+        assert!(!is_overload(&Stats::DecodeErr));
+        assert!(!is_overload(&Stats::DNSErr));
+        assert!(!is_overload(&Stats::IOErr));
+        assert!(!is_overload(&Stats::TlsErr));
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_concurrency() {
+        let concurrency = Arc::new(AdaptiveConcurrency::new(100, 10, 120));
+        // 10 failures out of 100 halve the limit, in-flight slots are taken back when done
+        let held: Vec<_> = futures::future::join_all((0..80).map(|_| concurrency.acquire())).await;
+        for i in 0..100 {
+            let changed = concurrency.record(i % 10 == 0);
+            assert_eq!(changed.is_some(), i == 99);
+        }
+        assert_eq!(concurrency.limit(), 50);
+        drop(held);
+        let held: Vec<_> = futures::future::join_all((0..50).map(|_| concurrency.acquire())).await;
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), concurrency.acquire()).await.is_err());
+        drop(held);
+
+        // a clean window grows it by a step, up to the max
+        for _ in 0..50 {
+            concurrency.record(false);
+        }
+        assert_eq!(concurrency.limit(), 60);
+        for _ in 0..1000 {
+            concurrency.record(false);
+        }
+        assert_eq!(concurrency.limit(), 120);
+
+        // never below the min
+        for _ in 0..1000 {
+            concurrency.record(true);
+        }
+        assert_eq!(concurrency.limit(), 10);
+    }
+}
+
+#[cfg(test)]
+mod throttle_test {
+    use std::fs::{remove_file, write};
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use futures::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use kenshi::{Params, Scanner, Stats};
+    use kenshi::throttle::{HostBackoff, is_throttled, parse_retry_after, ThrottleAction, Verdict};
+
+    #[test]
+    fn test_throttled_status() {
+        assert!(is_throttled(429, false));
+        assert!(is_throttled(503, true));
+        // a backend down, not a rate limit
+        assert!(!is_throttled(503, false));
+        assert!(!is_throttled(500, true));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = UNIX_EPOCH + Duration::from_secs(1445412480);
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+        // 2015-10-21 07:28:00, 30s after now
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now), Some(Duration::from_secs(30)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("-1", SystemTime::now()), None);
+        assert_eq!(parse_retry_after("soon", SystemTime::now()), None);
+    }
+
+    #[tokio::test]
+    async fn test_host_backoff() {
+        let backoff = HostBackoff::new(2, ThrottleAction::Drop, 10);
+        let url = "http://a.example.com/x";
+        assert_eq!(backoff.throttled(url, Some(Duration::from_millis(100))), Verdict::Retry);
+        // in-flight responses of the same burst don't count as another pause
+        assert_eq!(backoff.throttled(url, Some(Duration::from_millis(100))), Verdict::Retry);
+        let start = std::time::Instant::now();
+        assert!(backoff.wait(url).await);
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        // other hosts aren't paused
+        let start = std::time::Instant::now();
+        assert!(backoff.wait("http://b.example.com/x").await);
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        // a good response resets the count
+        backoff.success(url);
+        assert_eq!(backoff.throttled(url, Some(Duration::ZERO)), Verdict::Retry);
+        assert_eq!(backoff.throttled(url, Some(Duration::ZERO)), Verdict::Retry);
+        assert_eq!(backoff.throttled(url, Some(Duration::ZERO)), Verdict::DropHost);
+        assert!(!backoff.wait("http://a.example.com/y").await);
+        assert_eq!(backoff.dropped(), vec!["http://a.example.com".to_string()]);
+
+        let backoff = HostBackoff::new(1, ThrottleAction::Abort, 10);
+        assert_eq!(backoff.throttled(url, Some(Duration::ZERO)), Verdict::Retry);
+        assert_eq!(backoff.throttled(url, Some(Duration::ZERO)), Verdict::Abort);
+    }
+
+    #[tokio::test]
+    async fn test_stop_while_throttled() {
+        // every request is told to come back in 5 minutes
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.read(&mut [0u8; 1024]).await;
+                let _ = stream.write_all(b"HTTP/1.1 429 Too Many Requests\r\nRetry-After: 300\r\nContent-Length: 0\r\n\r\n").await;
+            }
+        });
+        let words = "/tmp/kenshi_stop_throttled.txt";
+        write(words, "a\nb\nc").unwrap();
+        let params = Params::builder()
+            .url(&format!("http://{addr}/FUZZ"))
+            .wordlist(words)
+            .build()
+            .unwrap();
+        let scanner = Scanner::new(params);
+        let (stats, stop) = (scanner.stats(), scanner.stop_handle());
+        let scan = tokio::spawn(async move { scanner.run().count().await });
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        stop.store(false, Ordering::SeqCst);
+        // the pause is cut short, the payloads weren't answered and aren't done
+        tokio::time::timeout(Duration::from_secs(5), scan).await.unwrap().unwrap();
+        assert!(stats.get(&Stats::Throttled) > 0);
+        assert_eq!(stats.get(&Stats::Done), 0);
+        let _ = remove_file(words);
+    }
+}
+
+#[cfg(test)]
+mod retry_test {
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use kenshi::Stats;
+    use kenshi::error::{classify, error_stat, ErrorClass};
+    use kenshi::retry::{MAX_RETRY_DELAY, parse_classes, RetryPolicy};
+
+    #[test]
+    fn test_retry_policy() {
+        assert_eq!(parse_classes("timeout, reset").unwrap(), vec![ErrorClass::Timeout, ErrorClass::Reset]);
+        assert_eq!(parse_classes("all").unwrap(), ErrorClass::all());
+        assert!(parse_classes("none").unwrap().is_empty());
+        assert!(parse_classes("timeout,nxdomain").is_err());
+
+        let policy = RetryPolicy::default();
+        assert!(policy.retries(&ErrorClass::Reset));
+        assert!(!policy.retries(&ErrorClass::Dns));
+        assert!(!policy.retries(&ErrorClass::Tls));
+        let policy = RetryPolicy { classes: vec![], base_delay: Duration::from_millis(100) };
+        for _ in 0..20 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100), "{delay:?}");
+            let delay = policy.delay(4);
+            assert!(delay >= Duration::from_millis(400) && delay <= Duration::from_millis(800), "{delay:?}");
+        }
+        assert!(policy.delay(100) <= MAX_RETRY_DELAY);
+    }
+
+    // a server that closes the connection, or answers garbage to a TLS hello
+    async fn serve(reply: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(reply).await;
+            }
+        });
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_classify() {
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(5)).build().unwrap();
+        let addr = serve(b"").await;
+        let e = client.get(format!("http://{addr}/")).send().await.unwrap_err();
+        assert_eq!(classify(&e), ErrorClass::Reset);
+        let addr = serve(b"HTTP/1.1 200 OK\r\n\r\n").await;
+        let e = client.get(format!("https://{addr}/")).send().await.unwrap_err();
+        assert_eq!(classify(&e), ErrorClass::Tls);
+    }
+
+    // trust_dns is named hickory_dns on newer reqwest, the scanner calls it too
+    #[allow(deprecated)]
+    #[tokio::test]
+    async fn test_dns_error() {
+        // the resolver the scanner uses, .invalid never resolves
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .trust_dns(true)
+            .build()
+            .unwrap();
+        let e = client.get("http://does-not-exist.invalid/").send().await.unwrap_err();
+        assert_eq!(classify(&e), ErrorClass::Dns);
+        assert_eq!(error_stat(&e, false), Stats::DNSErr);
+        assert!(!RetryPolicy::default().retries(&classify(&e)));
+        assert!(!kenshi::concurrency::is_overload(&error_stat(&e, false)));
+    }
+
+    #[tokio::test]
+    async fn test_error_stat() {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .redirect(reqwest::redirect::Policy::limited(2))
+            .build()
+            .unwrap();
+        // nothing listens there
+        let e = client.get("http://127.0.0.1:1/").send().await.unwrap_err();
+        assert_eq!(classify(&e), ErrorClass::Refused);
+        assert_eq!(error_stat(&e, false), Stats::ConnRefused);
+        assert_eq!(error_stat(&e, true), Stats::ProxyErr);
+        let addr = serve(b"").await;
+        let e = client.get(format!("http://{addr}/")).send().await.unwrap_err();
+        assert_eq!(error_stat(&e, false), Stats::ConnReset);
+        let addr = serve(b"HTTP/1.1 200 OK\r\n\r\n").await;
+        let e = client.get(format!("https://{addr}/")).send().await.unwrap_err();
+        assert_eq!(error_stat(&e, false), Stats::TlsErr);
+        let addr = serve(b"HTTP/1.1 302 Found\r\nLocation: /\r\nContent-Length: 0\r\n\r\n").await;
+        let e = client.get(format!("http://{addr}/")).send().await.unwrap_err();
+        assert_eq!(error_stat(&e, false), Stats::RedirectErr);
+        let addr = serve(b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 4\r\n\r\nnope").await;
+        let e = client.get(format!("http://{addr}/")).send().await.unwrap().bytes().await.unwrap_err();
+        assert_eq!(error_stat(&e, false), Stats::DecodeErr);
+
+        let client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::all("http://127.0.0.1:1").unwrap())
+            .build()
+            .unwrap();
+        let e = client.get("http://example.com/").send().await.unwrap_err();
+        assert_eq!(error_stat(&e, true), Stats::ProxyErr);
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use tokio::time::{Instant, sleep_until};
+
+use crate::lru::{host_of, LruCache};
+
+// pause after a throttled response without Retry-After, doubled for each round in a row
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+// a Retry-After of a day would stall the scan
+pub const MAX_BACKOFF: Duration = Duration::from_secs(300);
+// throttled responses a payload is sent again for, the last one is matched as it is
+pub const MAX_PAYLOAD_THROTTLED: usize = 10;
+
+/// A 429, or a 503 with Retry-After. A bare 503 is a broken backend more often than a limit.
+pub fn is_throttled(status: u16, retry_after: bool) -> bool {
+    status == 429 || (status == 503 && retry_after)
+}
+
+/// Retry-After in its delta-seconds ("120") or HTTP-date
+/// ("Wed, 21 Oct 2015 07:28:00 GMT") form, as a delay from `now`.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    // a date already past means right away
+    Some(date.duration_since(now).unwrap_or_default())
+}
+
+/// What happens to a host still throttling after --max-throttle rounds
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThrottleAction {
+    // its remaining payloads are skipped, the other hosts go on
+    Drop,
+    // the scan stops, it can be resumed later
+    Abort,
+}
+
+impl FromStr for ThrottleAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "drop" => Ok(ThrottleAction::Drop),
+            "abort" => Ok(ThrottleAction::Abort),
+            _ => Err(format!("[params] unknown throttle action \"{s}\", expected drop or abort")),
+        }
+    }
+}
+
+impl fmt::Display for ThrottleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ThrottleAction::Drop => "drop",
+            ThrottleAction::Abort => "abort",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    // send the request again once the host pause is over
+    Retry,
+    DropHost,
+    Abort,
+}
+
+#[derive(Clone, Debug)]
+struct HostState {
+    // no request to the host before this
+    until: Instant,
+    // pauses in a row, reset by a response that isn't throttled
+    rounds: usize,
+}
+
+/// Pauses a host answering 429, or 503 with Retry-After, for its Retry-After or an exponential
+/// backoff. Throttled responses arriving during a pause don't start another
+/// one, a burst of in-flight requests is a single round.
+pub struct HostBackoff {
+    // 0 never gives up
+    max_rounds: usize,
+    action: ThrottleAction,
+    hosts: Mutex<LruCache<HostState>>,
+    // hosts given up on, dropped or the reason the scan stopped
+    given_up: Mutex<BTreeSet<String>>,
+}
+
+impl HostBackoff {
+    pub fn new(max_rounds: usize, action: ThrottleAction, capacity: usize) -> Self {
+        Self {
+            max_rounds,
+            action,
+            hosts: Mutex::new(LruCache::new(capacity)),
+            given_up: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Wait while the host is paused, false once it was dropped
+    pub async fn wait(&self, url: &str) -> bool {
+        let host = match host_of(url) {
+            Some(host) => host,
+            None => return true,
+        };
+        if self.given_up.lock().unwrap().contains(&host) {
+            return false;
+        }
+        let state = self.hosts.lock().unwrap().get(&host);
+        if let Some(state) = state {
+            sleep_until(state.until).await;
+        }
+        !self.given_up.lock().unwrap().contains(&host)
+    }
+
+    /// A throttled response from the host, `retry_after` from its header
+    pub fn throttled(&self, url: &str, retry_after: Option<Duration>) -> Verdict {
+        let host = match host_of(url) {
+            Some(host) => host,
+            None => return Verdict::Retry,
+        };
+        let now = Instant::now();
+        let mut hosts = self.hosts.lock().unwrap();
+        let mut state = hosts.get(&host).unwrap_or(HostState { until: now, rounds: 0 });
+        if state.until > now {
+            return Verdict::Retry;
+        }
+        state.rounds += 1;
+        if self.max_rounds > 0 && state.rounds > self.max_rounds {
+            log::error!("[throttle] {host} still throttling after {} pauses, {}", self.max_rounds, match self.action {
+                ThrottleAction::Drop => "dropping the host",
+                ThrottleAction::Abort => "stopping the scan",
+            });
+            // the requests waiting on the host give up too
+            self.given_up.lock().unwrap().insert(host.clone());
+            hosts.insert(&host, state);
+            return match self.action {
+                ThrottleAction::Drop => Verdict::DropHost,
+                ThrottleAction::Abort => Verdict::Abort,
+            };
+        }
+        let delay = retry_after
+            .unwrap_or_else(|| DEFAULT_BACKOFF.saturating_mul(1 << (state.rounds - 1).min(16)))
+            .min(MAX_BACKOFF);
+        log::warn!("[throttle] {host} throttled, pausing {:.1}s", delay.as_secs_f64());
+        state.until = now + delay;
+        hosts.insert(&host, state);
+        Verdict::Retry
+    }
+
+    /// A response that isn't throttled, the host is fine again
+    pub fn success(&self, url: &str) {
+        let host = match host_of(url) {
+            Some(host) => host,
+            None => return,
+        };
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(mut state) = hosts.get(&host) {
+            if state.rounds > 0 {
+                state.rounds = 0;
+                hosts.insert(&host, state);
+            }
+        }
+    }
+
+    /// Hosts dropped with --throttle-action drop
+    pub fn dropped(&self) -> Vec<String> {
+        if self.action != ThrottleAction::Drop {
+            return vec![];
+        }
+        self.given_up.lock().unwrap().iter().cloned().collect()
+    }
+}
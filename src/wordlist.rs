@@ -0,0 +1,484 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+pub const DEFAULT_KEYWORD: &str = "FUZZ";
+// --dedup: odds of a line never seen before being taken for a repeat
+pub const DEDUP_FALSE_POSITIVE: f64 = 0.0001;
+// the size of stdin isn't known, the filter is sized for this many lines
+pub const DEDUP_STDIN_LINES: usize = 10_000_000;
+// 256MB, past this the filter stops growing and the false positives go up
+const DEDUP_MAX_BITS: usize = 1 << 31;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WordlistSource {
+    File(String),
+    Stdin,
+}
+
+#[derive(Clone, Debug)]
+pub struct Wordlist {
+    pub source: WordlistSource,
+    pub keyword: String,
+    pub extensions: Option<Vec<String>>,
+    // 0 when the size is unknown (stdin)
+    pub len: usize,
+    // --dedup, repeated payloads are skipped
+    pub dedup: bool,
+}
+
+impl Wordlist {
+    pub fn from_file(path: &str, keyword: &str, extensions: Option<Vec<String>>) -> std::io::Result<Self> {
+        let len = count_lines(path, extensions.as_ref().map(|e| e.len()))?;
+        Ok(Self {
+            source: WordlistSource::File(path.to_string()),
+            keyword: keyword.to_string(),
+            extensions,
+            len,
+            dedup: false,
+        })
+    }
+
+    pub fn from_stdin(extensions: Option<Vec<String>>) -> Self {
+        Self {
+            source: WordlistSource::Stdin,
+            keyword: DEFAULT_KEYWORD.to_string(),
+            extensions,
+            len: 0,
+            dedup: false,
+        }
+    }
+
+    /// Skip repeated payloads while they are read, nothing is read ahead.
+    pub fn with_dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+
+    /// Lazily read payloads, expanding %EXT% and skipping the duplicates with --dedup.
+    pub fn payloads(&self) -> std::io::Result<Box<dyn Iterator<Item=String> + Send>> {
+        let lines = self.lines()?;
+        if !self.dedup {
+            return Ok(lines);
+        }
+        let name = match &self.source {
+            WordlistSource::File(path) => path.clone(),
+            WordlistSource::Stdin => String::from("stdin"),
+        };
+        Ok(Box::new(StreamDedup::new(lines, self.len, name)))
+    }
+
+    // every line, %EXT% expanded
+    fn lines(&self) -> std::io::Result<Box<dyn Iterator<Item=String> + Send>> {
+        let lines: Box<dyn Iterator<Item=String> + Send> = match &self.source {
+            WordlistSource::File(path) => {
+                let reader = BufReader::new(File::open(path)?);
+                Box::new(reader.lines().map_while(Result::ok))
+            }
+            WordlistSource::Stdin => {
+                Box::new(BufReader::new(std::io::stdin()).lines().map_while(Result::ok))
+            }
+        };
+        let lines: Box<dyn Iterator<Item=String> + Send> = match self.extensions.clone() {
+            Some(ext_s) => {
+                Box::new(lines.flat_map(move |line| {
+                    if line.contains("%EXT%") {
+                        return ext_s.iter().map(|s| line.replace("%EXT%", s)).collect();
+                    }
+                    vec![line]
+                }))
+            }
+            None => lines
+        };
+        Ok(lines)
+    }
+}
+
+/// Bloom filter of the payloads seen, about 2.4 bytes per expected item.
+/// The hashes are seeded the same on every run, a resumed scan skips the same lines.
+struct Bloom {
+    bits: Vec<u64>,
+    len: usize,
+    hashes: u32,
+}
+
+impl Bloom {
+    fn new(expected: usize) -> Self {
+        let expected = expected.max(1024) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let len = (-expected * DEDUP_FALSE_POSITIVE.ln() / (ln2 * ln2)).ceil() as usize;
+        let len = len.min(DEDUP_MAX_BITS);
+        let hashes = ((len as f64 / expected) * ln2).round().clamp(1.0, 16.0) as u32;
+        Self {
+            bits: vec![0; len / 64 + 1],
+            len,
+            hashes,
+        }
+    }
+
+    /// Add the item, false when it was (probably) there already
+    fn insert<T: Hash>(&mut self, item: &T) -> bool {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        let h1 = hasher.finish();
+        // double hashing, the second hash is odd so every step moves
+        let h2 = h1.rotate_left(32).wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+        let mut new = false;
+        for i in 0..self.hashes as u64 {
+            let bit = (h1.wrapping_add(i.wrapping_mul(h2)) % self.len as u64) as usize;
+            let (word, mask) = (bit / 64, 1u64 << (bit % 64));
+            if self.bits[word] & mask == 0 {
+                self.bits[word] |= mask;
+                new = true;
+            }
+        }
+        new
+    }
+}
+
+// drops the items seen before, in the order they are read
+struct StreamDedup<T> {
+    items: Box<dyn Iterator<Item=T> + Send>,
+    seen: Bloom,
+    dropped: usize,
+    name: String,
+}
+
+impl<T> StreamDedup<T> {
+    // `expected` is the number of items, 0 when unknown
+    fn new(items: Box<dyn Iterator<Item=T> + Send>, expected: usize, name: String) -> Self {
+        let expected = if expected == 0 { DEDUP_STDIN_LINES } else { expected };
+        Self {
+            items,
+            seen: Bloom::new(expected),
+            dropped: 0,
+            name,
+        }
+    }
+}
+
+impl<T: Hash> Iterator for StreamDedup<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for item in self.items.by_ref() {
+            if self.seen.insert(&item) {
+                return Some(item);
+            }
+            self.dropped += 1;
+        }
+        if self.dropped > 0 {
+            log::info!("[wordlist] {}: {} duplicate lines dropped", self.name, self.dropped);
+            self.dropped = 0;
+        }
+        None
+    }
+}
+
+/// Split the `-w` value into path and keyword, eg. '/path/to/wordlist:KEYWORD'
+pub fn split_keyword(value: &str) -> (String, String) {
+    if let Some((path, keyword)) = value.rsplit_once(':') {
+        if !keyword.is_empty() && keyword.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return (path.to_string(), keyword.to_string());
+        }
+    }
+    (value.to_string(), DEFAULT_KEYWORD.to_string())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FuzzMode {
+    // every combination of all wordlists
+    Clusterbomb,
+    // wordlists are read in parallel, stops at the shortest
+    Pitchfork,
+    // one keyword at a time, the others are left empty
+    Sniper,
+}
+
+impl FromStr for FuzzMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clusterbomb" => Ok(FuzzMode::Clusterbomb),
+            "pitchfork" => Ok(FuzzMode::Pitchfork),
+            "sniper" => Ok(FuzzMode::Sniper),
+            _ => Err(format!("unknown mode \"{s}\". Available modes: clusterbomb, pitchfork, sniper")),
+        }
+    }
+}
+
+impl fmt::Display for FuzzMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FuzzMode::Clusterbomb => "clusterbomb",
+            FuzzMode::Pitchfork => "pitchfork",
+            FuzzMode::Sniper => "sniper",
+        })
+    }
+}
+
+/// One value per keyword
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Payload {
+    pub words: Vec<(String, String)>,
+}
+
+impl Payload {
+    pub fn fill(&self, template: &str) -> String {
+        let mut filled = template.to_string();
+        for (keyword, value) in self.words.iter() {
+            filled = filled.replace(keyword, value);
+        }
+        filled
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.words.len() == 1 {
+            return f.write_str(&self.words[0].1);
+        }
+        let words: Vec<String> = self.words.iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect();
+        f.write_str(&words.join(","))
+    }
+}
+
+type Words = Box<dyn Iterator<Item=String> + Send>;
+
+#[derive(Clone, Debug)]
+pub struct WordlistSet {
+    pub lists: Vec<Wordlist>,
+    pub mode: FuzzMode,
+    // --dedup in pitchfork mode applies to the pairs, the lists stay aligned
+    dedup: bool,
+}
+
+impl WordlistSet {
+    pub fn new(lists: Vec<Wordlist>, mode: FuzzMode) -> Self {
+        Self {
+            lists,
+            mode,
+            dedup: false,
+        }
+    }
+
+    pub fn keywords(&self) -> Vec<String> {
+        self.lists.iter().map(|w| w.keyword.clone()).collect()
+    }
+
+    /// Number of payloads the mode produces, 0 when unknown.
+    /// The repeats --dedup skips are counted, they are only found while reading.
+    pub fn len(&self) -> usize {
+        if self.lists.iter().any(|w| w.len == 0) {
+            return 0;
+        }
+        let lens = self.lists.iter().map(|w| w.len);
+        match self.mode {
+            FuzzMode::Clusterbomb => lens.fold(1usize, |acc, n| acc.saturating_mul(n)),
+            FuzzMode::Pitchfork => lens.min().unwrap_or(0),
+            FuzzMode::Sniper => lens.sum(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lists.is_empty()
+    }
+
+    /// Size in bytes of every wordlist file, 0 for stdin
+    pub fn file_sizes(&self) -> Vec<u64> {
+        self.lists.iter().map(|w| match &w.source {
+            WordlistSource::File(path) => std::fs::metadata(path).map_or(0, |m| m.len()),
+            WordlistSource::Stdin => 0,
+        }).collect()
+    }
+
+    /// --dedup on every wordlist, on the combined pairs in pitchfork mode:
+    /// a repeat in one list would shift all the pairs after it.
+    pub fn with_dedup(mut self) -> Self {
+        if self.mode == FuzzMode::Pitchfork {
+            self.dedup = true;
+            return self;
+        }
+        self.lists = self.lists.into_iter().map(|w| w.with_dedup()).collect();
+        self
+    }
+
+    /// Lazily generate the keyword combinations, nothing is kept in memory.
+    pub fn payloads(&self) -> std::io::Result<Box<dyn Iterator<Item=Payload> + Send>> {
+        let keywords = self.keywords();
+        match self.mode {
+            FuzzMode::Clusterbomb => {
+                Ok(Box::new(Clusterbomb::new(self.lists.clone())?))
+            }
+            FuzzMode::Pitchfork => {
+                let pairs = self.pitchfork()?;
+                if !self.dedup {
+                    return Ok(pairs);
+                }
+                Ok(Box::new(StreamDedup::new(pairs, self.len(), String::from("pitchfork"))))
+            }
+            FuzzMode::Sniper => {
+                let mut chained: Box<dyn Iterator<Item=Payload> + Send> = Box::new(std::iter::empty());
+                for (i, list) in self.lists.iter().enumerate() {
+                    // opened on demand, one file at a time
+                    let list = list.clone();
+                    let keywords = keywords.clone();
+                    let position = std::iter::once(()).flat_map(move |_| {
+                        let words: Words = match list.payloads() {
+                            Ok(w) => w,
+                            Err(e) => {
+                                log::error!("wordlist read failed. {e}");
+                                Box::new(std::iter::empty())
+                            }
+                        };
+                        let keywords = keywords.clone();
+                        words.map(move |value| {
+                            let words = keywords.iter().enumerate().map(|(n, k)| {
+                                (k.clone(), if n == i { value.clone() } else { String::new() })
+                            }).collect();
+                            Payload { words }
+                        })
+                    });
+                    chained = Box::new(chained.chain(position));
+                }
+                Ok(chained)
+            }
+        }
+    }
+}
+
+impl WordlistSet {
+    // the lists read side by side, stops at the shortest
+    fn pitchfork(&self) -> std::io::Result<Box<dyn Iterator<Item=Payload> + Send>> {
+        let keywords = self.keywords();
+        let mut iters = vec![];
+        for list in self.lists.iter() {
+            iters.push(list.payloads()?);
+        }
+        Ok(Box::new(std::iter::from_fn(move || {
+            let mut words = vec![];
+            for (i, iter) in iters.iter_mut().enumerate() {
+                words.push((keywords[i].clone(), iter.next()?));
+            }
+            Some(Payload { words })
+        })))
+    }
+}
+
+/// Cartesian product of the wordlists. The last wordlist varies fastest and
+/// is re-read from the start each time the previous one moves on.
+struct Clusterbomb {
+    lists: Vec<Wordlist>,
+    iters: Vec<Words>,
+    current: Vec<String>,
+    done: bool,
+}
+
+impl Clusterbomb {
+    fn new(lists: Vec<Wordlist>) -> std::io::Result<Self> {
+        let mut iters = vec![];
+        for list in lists.iter() {
+            iters.push(list.payloads()?);
+        }
+        Ok(Self {
+            lists,
+            iters,
+            current: vec![],
+            done: false,
+        })
+    }
+}
+
+impl Iterator for Clusterbomb {
+    type Item = Payload;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.lists.is_empty() {
+            return None;
+        }
+        if self.current.is_empty() {
+            // first combination
+            for iter in self.iters.iter_mut() {
+                match iter.next() {
+                    Some(v) => self.current.push(v),
+                    None => {
+                        self.done = true;
+                        return None;
+                    }
+                }
+            }
+        } else {
+            let mut i = self.lists.len() - 1;
+            loop {
+                if let Some(v) = self.iters[i].next() {
+                    self.current[i] = v;
+                    break;
+                }
+                if i == 0 {
+                    self.done = true;
+                    return None;
+                }
+                // rewind this position and carry over to the previous one
+                match self.lists[i].payloads() {
+                    Ok(mut iter) => {
+                        match iter.next() {
+                            Some(v) => self.current[i] = v,
+                            None => {
+                                self.done = true;
+                                return None;
+                            }
+                        }
+                        self.iters[i] = iter;
+                    }
+                    Err(e) => {
+                        log::error!("wordlist read failed. {e}");
+                        self.done = true;
+                        return None;
+                    }
+                }
+                i -= 1;
+            }
+        }
+        let words = self.lists.iter()
+            .zip(self.current.iter())
+            .map(|(list, v)| (list.keyword.clone(), v.clone()))
+            .collect();
+        Some(Payload { words })
+    }
+}
+
+/// Count lines without keeping them, lines holding %EXT% count once per extension.
+pub fn count_lines(path: &str, ext_num: Option<usize>) -> std::io::Result<usize> {
+    let mut file = File::open(path)?;
+    if let Some(ext_num) = ext_num {
+        let reader = BufReader::new(file);
+        let mut total = 0;
+        for line in reader.lines() {
+            total += if line?.contains("%EXT%") { ext_num } else { 1 };
+        }
+        return Ok(total);
+    }
+
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0;
+    let mut last = b'\n';
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        total += buf[..n].iter().filter(|&&b| b == b'\n').count();
+        last = buf[n - 1];
+    }
+    // no trailing newline
+    if last != b'\n' {
+        total += 1;
+    }
+    Ok(total)
+}